@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use chrono::Duration;
 use chronoutil::shift_months;
 use chronoutil::shift_years;
@@ -9,6 +10,7 @@ use uuid::Uuid;
 use rust_decimal_macros::dec;
 
 use serde::Deserialize;
+use crate::calendar::{BusinessDayAdjustment, Calendar};
 use crate::serializer::*;
 
 /// Account models.
@@ -34,12 +36,51 @@ pub enum TransactionStatus {
     Recorded
 }
 
+/// What a transaction represents, so reports and checks can treat e.g. adjustments specially.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub enum TransactionKind {
+    #[default]
+    Normal,
+    /// A deliberate correction which need not balance, e.g. a single sided balance adjustment.
+    Adjustment,
+    Opening,
+    Transfer,
+    /// Closes revenue and expense accounts to equity at the end of a period.
+    Closing,
+}
+
+/// Where a transaction came from, for auditing.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Default)]
+pub enum TransactionSource {
+    /// Entered by a user.
+    #[default]
+    Manual,
+    /// Imported, with the id shared by the transactions of the same import.
+    Import { batch_id: Uuid },
+    /// Generated by a schedule.
+    Schedule { schedule_id: Uuid },
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Transaction {
     pub id: Uuid,
     pub entries: Vec<Entry>,
     pub status: TransactionStatus,
-    pub schedule_id: Option<Uuid>
+    /// Where the transaction came from, e.g. the schedule that generated it.
+    #[serde(default)]
+    pub source: TransactionSource,
+    #[serde(default)]
+    pub kind: TransactionKind,
+    /// Whether the transaction has been approved for posting, only checked when the books require approval.
+    #[serde(default = "default_true")]
+    pub approved: bool,
+    /// Ids of related transactions, e.g. a payable and its settlement.
+    #[serde(default)]
+    pub related: Vec<Uuid>,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Transaction {
@@ -61,11 +102,66 @@ impl Transaction {
         balance
     }
 
+    /// True when the debit entries total the same as the credit entries.
+    pub fn is_balanced(&self) -> bool {
+        let total = self.entries.iter().fold(Decimal::ZERO, |total, e| total + e.signed_amount(Side::Debit));
+        total.is_zero()
+    }
+
+    /// Adjust the amount of the first entry for the given account by whatever is needed for the transaction
+    /// to balance, e.g. to absorb the rounding left over from splitting an amount by percentages. Does
+    /// nothing if the account has no entry.
+    pub fn balance_to(&mut self, account_id: Uuid) {
+        let residual = self.entries.iter().fold(Decimal::ZERO, |total, e| total + e.signed_amount(Side::Debit));
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.account_id == account_id) {
+            match entry.entry_type {
+                Side::Debit => entry.amount -= residual,
+                Side::Credit => entry.amount += residual,
+            }
+        }
+    }
+
+    /// One line summary for display, e.g. "2022-06-04: received moneys — Dr Savings 10000, Cr Loan 10000".
+    /// Accounts not in the given map are shown by id.
+    pub fn summary(&self, accounts: &HashMap<Uuid, Account>) -> String {
+        let first = match self.entries.first() {
+            Some(e) => e,
+            None => return "(no entries)".to_string(),
+        };
+        let postings: Vec<String> = self.entries
+            .iter()
+            .map(|e| {
+                let side = match e.entry_type {
+                    Side::Debit => "Dr",
+                    Side::Credit => "Cr",
+                };
+                let name = accounts.get(&e.account_id).map(|a| a.name.clone()).unwrap_or(e.account_id.to_string());
+                format!("{} {} {}", side, name, e.amount)
+            })
+            .collect();
+        format!("{}: {} — {}", first.date, first.description, postings.join(", "))
+    }
+
+    /// Id of the schedule that generated the transaction, if any.
+    pub fn schedule_id(&self) -> Option<Uuid> {
+        match self.source {
+            TransactionSource::Schedule { schedule_id } => Some(schedule_id),
+            _ => None,
+        }
+    }
+
+    /// Date of the transaction, taken from its first entry.
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.entries.first().map(|e| e.date)
+    }
+
     pub fn involves_account(&self, account_id: &Uuid) -> bool {
         self.entries.iter()
                 .any(|e| e.account_id == *account_id)
     }
 
+    /// Find the first entry for the given account. Where an account appears in more than one
+    /// entry only the first, in entry order, is returned; use `account_entries` to get them all.
     pub fn find_entry_by_account(&self, account_id: &Uuid) -> Option<&Entry> {
         self.entries
             .iter()
@@ -74,7 +170,9 @@ impl Transaction {
 
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
+#[cfg_attr(not(feature = "integer_amounts"), derive(Serialize))]
+#[cfg_attr(feature = "integer_amounts", serde(try_from = "crate::minor_units::StoredEntry"))]
 pub struct Entry {
     pub id: Uuid,
     pub transaction_id: Uuid,
@@ -86,6 +184,22 @@ pub struct Entry {
     pub entry_type: Side,
     pub amount: Decimal,
     pub balance: Option<Decimal>,
+    /// Currency of a foreign currency entry, whose `amount` is the converted book currency amount.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Amount of a foreign currency entry in its own currency.
+    #[serde(default)]
+    pub foreign_amount: Option<Decimal>,
+    /// Ticked off against a statement.
+    #[serde(default)]
+    pub reconciled: bool,
+    /// Left out of user facing reports, e.g. internal reclassifications. Still counted in balances and
+    /// the trial balance.
+    #[serde(default)]
+    pub exclude_from_reports: bool,
+    /// Tags beyond the account, e.g. {"project": "X", "department": "Sales"}.
+    #[serde(default)]
+    pub dimensions: HashMap<String, String>,
 }
 
 impl Entry {
@@ -104,19 +218,87 @@ impl Entry {
         self.set_balance(Some(balance.clone()));
         balance
     }
+
+    /// The amount of this entry signed against an account's normal balance side,
+    /// i.e. positive when it increases the account's balance.
+    pub fn signed_amount(&self, normal_balance: Side) -> Decimal {
+        if self.entry_type == normal_balance {
+            self.amount
+        } else {
+            -self.amount
+        }
+    }
+}
+/// Entry in the shape expected by external systems, which use `transaction_date` and `memo` in place of
+/// `date` and `description`. The native `Entry` format remains the default.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ExternalEntry {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    #[serde(rename = "transaction_date")]
+    #[serde(serialize_with = "serialize_naivedate")]
+    #[serde(deserialize_with = "deserialize_naivedate")]
+    pub date: NaiveDate,
+    #[serde(rename = "memo")]
+    pub description: String,
+    pub account_id: Uuid,
+    pub entry_type: Side,
+    pub amount: Decimal,
+    pub balance: Option<Decimal>,
+}
+
+impl Entry {
+    pub fn to_external(&self) -> ExternalEntry {
+        ExternalEntry {
+            id: self.id,
+            transaction_id: self.transaction_id,
+            date: self.date,
+            description: self.description.clone(),
+            account_id: self.account_id,
+            entry_type: self.entry_type,
+            amount: self.amount,
+            balance: self.balance,
+        }
+    }
+
+    pub fn from_external(external: ExternalEntry) -> Entry {
+        Entry {
+            id: external.id,
+            transaction_id: external.transaction_id,
+            date: external.date,
+            description: external.description,
+            account_id: external.account_id,
+            entry_type: external.entry_type,
+            amount: external.amount,
+            balance: external.balance,
+            currency: None,
+            foreign_amount: None,
+            reconciled: false,
+            exclude_from_reports: false,
+            dimensions: HashMap::new(),
+        }
+    }
 }
+
 pub struct Transaction2 {
     pub id: Uuid,
     pub events: Vec<Entry>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// A grouping of accounts within an account type, e.g. "Current Assets", for subtotals in reports.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct AccountCategory {
-    name: String,
-    normal_balance: Side,
+    pub name: String,
+    pub normal_balance: Side,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl AccountCategory {
+    pub fn new(name: &str, normal_balance: Side) -> AccountCategory {
+        AccountCategory { name: name.to_string(), normal_balance }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AccountType {
     Asset,
     Liability,
@@ -136,6 +318,11 @@ impl AccountType {
         }
     }
 
+    /// True for the revenue and expense accounts reported on the income statement.
+    pub fn is_income_statement(&self) -> bool {
+        matches!(self, Self::Revenue | Self::Expense)
+    }
+
     pub fn order(&self) -> u8{
         match *self {
             Self::Asset => 0,
@@ -147,13 +334,41 @@ impl AccountType {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Deserialize)]
+#[cfg_attr(not(feature = "integer_amounts"), derive(Serialize))]
+#[cfg_attr(feature = "integer_amounts", serde(try_from = "crate::minor_units::StoredAccount"))]
 pub struct Account {
     pub id: Uuid,
     pub name: String,
     pub account_type: AccountType,
     pub balance: Decimal,
-    pub starting_balance: Decimal
+    pub starting_balance: Decimal,
+    /// Date the starting balance is as at, e.g. when migrating mid-year. Earlier entries are already part of
+    /// the starting balance so aren't added to it. None means the start of time.
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_option_naivedate")]
+    #[serde(deserialize_with = "deserialize_option_naivedate")]
+    pub starting_balance_date: Option<NaiveDate>,
+    /// Date the account was opened, no entries should be dated before it.
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_option_naivedate")]
+    #[serde(deserialize_with = "deserialize_option_naivedate")]
+    pub opened: Option<NaiveDate>,
+    /// ISO currency code of the account, if set.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Code from the chart of accounts, e.g. "1000".
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Amount budgeted for the account each month, e.g. for an expense category.
+    #[serde(default)]
+    pub monthly_budget: Option<Decimal>,
+    /// Archived accounts are hidden from `Books::active_accounts` but kept for their history.
+    #[serde(default = "default_true")]
+    pub active: bool,
+    /// Category the account is grouped under, its normal balance must match the account type's.
+    #[serde(default)]
+    pub category: Option<AccountCategory>,
 }
 
 impl Account {
@@ -164,12 +379,25 @@ impl Account {
             account_type,
             balance: dec!(0),
             starting_balance: dec!(0),
+            starting_balance_date: None,
+            opened: None,
+            currency: None,
+            code: None,
+            monthly_budget: None,
+            active: true,
+            category: None,
         }
     }
 
     pub fn normal_balance(&self) -> Side {
         self.account_type.normal_balance()
     }
+
+    /// True if an entry on the date adds to the starting balance, i.e. it isn't before the starting
+    /// balance date.
+    pub fn after_starting_balance(&self, date: NaiveDate) -> bool {
+        !self.starting_balance_date.is_some_and(|d| date < d)
+    }
 }
 
 
@@ -180,6 +408,59 @@ pub struct ScheduleEntry {
     pub account_id: Uuid,
     pub entry_type: Side,
     pub amount: Decimal,
+    /// Fixed changes to the amount from given dates, e.g. a rent increase each January.
+    #[serde(default)]
+    pub step_schedule: StepSchedule,
+}
+
+impl ScheduleEntry {
+    /// The entry this produces in the given transaction on the given date, with any step amount applied.
+    pub fn to_entry(&self, transaction_id: Uuid, date: NaiveDate) -> Entry {
+        Entry{
+            id: Uuid::new_v4(),
+            transaction_id,
+            description: self.description.clone(),
+            amount: self.step_schedule.amount_on(date).unwrap_or(self.amount),
+            account_id: self.account_id,
+            entry_type: self.entry_type,
+            date,
+            balance: None,
+            currency: None,
+            foreign_amount: None,
+            reconciled: false,
+            exclude_from_reports: false,
+            dimensions: HashMap::new(),
+        }
+    }
+}
+
+/// Amounts taking effect from given dates, in place of a schedule entry's amount.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct StepSchedule {
+    pub steps: Vec<AmountStep>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AmountStep {
+    #[serde(serialize_with = "serialize_naivedate")]
+    #[serde(deserialize_with = "deserialize_naivedate")]
+    pub effective_date: NaiveDate,
+    pub amount: Decimal,
+}
+
+impl StepSchedule {
+    pub fn add_step(&mut self, effective_date: NaiveDate, amount: Decimal) {
+        self.steps.push(AmountStep { effective_date, amount });
+    }
+
+    /// Amount of the step with the latest effective date on or before the given date, if any.
+    pub fn amount_on(&self, date: NaiveDate) -> Option<Decimal> {
+        self.steps
+            .iter()
+            .filter(|s| s.effective_date <= date)
+            .max_by_key(|s| s.effective_date)
+            .map(|s| s.amount)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
@@ -187,9 +468,77 @@ pub enum ScheduleEnum{
     Days,
     Weeks,
     Months,
+    Quarters,
     Years
 }
 
+impl ScheduleEnum {
+    /// Shift a date forward by the given number of periods.
+    pub fn shift(&self, date: NaiveDate, frequency: i64) -> NaiveDate {
+        match self {
+            ScheduleEnum::Days => date.checked_add_signed(Duration::days(frequency)).unwrap(),
+            ScheduleEnum::Weeks => date.checked_add_signed(Duration::days(frequency * 7)).unwrap(),
+            ScheduleEnum::Months => shift_months(date, frequency.try_into().unwrap()),
+            ScheduleEnum::Quarters => shift_months(date, (frequency * 3).try_into().unwrap()),
+            ScheduleEnum::Years => shift_years(date, frequency.try_into().unwrap()),
+        }
+    }
+}
+
+/// Calculates a schedule's entry amounts when a transaction is generated, in place of the fixed amounts.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum ModifierKind {
+    /// A percentage of an account's balance, e.g. interest on a savings account.
+    PercentOfAccountBalance { account_id: Uuid, percentage: Decimal },
+    /// An equal share of a total over a number of periods, the last period absorbing any rounding, e.g.
+    /// straight line depreciation of a fixed asset.
+    StraightLine { total: Decimal, periods: u32 },
+}
+
+/// Modifies the amounts of a schedule's entries. Amounts are recalculated on the modifier's own dates,
+/// from the start date then every `frequency` periods, and occurrences use the amount calculated on the
+/// latest modifier date on or before them. Occurrences before the start date keep their fixed amounts.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Modifier {
+    pub kind: ModifierKind,
+    #[serde(serialize_with = "serialize_naivedate")]
+    #[serde(deserialize_with = "deserialize_naivedate")]
+    pub start_date: NaiveDate,
+    pub period: ScheduleEnum,
+    pub frequency: i64,
+}
+
+impl Modifier {
+    pub fn get_next_modifier_date(&self, date: NaiveDate) -> NaiveDate {
+        self.period.shift(date, self.frequency)
+    }
+
+    /// The latest modifier date on or before the given date.
+    pub fn modifier_date_for(&self, date: NaiveDate) -> Option<NaiveDate> {
+        if date < self.start_date || self.frequency < 1 {
+            return None
+        }
+        let mut modifier_date = self.start_date;
+        let mut next = self.get_next_modifier_date(modifier_date);
+        while next <= date {
+            modifier_date = next;
+            next = self.get_next_modifier_date(modifier_date);
+        }
+        Some(modifier_date)
+    }
+}
+
+/// How monthly and yearly schedules handle an occurrence falling on a day the month doesn't have,
+/// e.g. the 31st.
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+pub enum MonthEndPolicy {
+    /// Use the last day of the shorter month (Jan 31 then Feb 28), returning to the start day after.
+    #[default]
+    Clamp,
+    /// Skip months that don't have the start day (Jan 31 then Mar 31).
+    Preserve,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Schedule {
     pub id: Uuid,
@@ -205,58 +554,193 @@ pub struct Schedule {
     #[serde(serialize_with = "serialize_option_naivedate")]
     #[serde(deserialize_with = "deserialize_option_naivedate")]
     pub last_date: Option<NaiveDate>,
-    pub entries: Vec<ScheduleEntry>
+    pub entries: Vec<ScheduleEntry>,
+    /// Occurrence dates to skip without generating a transaction.
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_vec_naivedate")]
+    #[serde(deserialize_with = "deserialize_vec_naivedate")]
+    pub skip_dates: Vec<NaiveDate>,
+    #[serde(default)]
+    pub month_end_policy: MonthEndPolicy,
+    #[serde(default)]
+    pub modifier: Option<Modifier>,
+    /// Inactive schedules are kept but don't generate transactions.
+    #[serde(default = "default_true")]
+    pub active: bool,
+    /// Free form notes for the user, e.g. "Cancel after the lease ends". Not used in generation.
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Stop after this many transactions have been generated, e.g. a loan with 36 payments.
+    #[serde(default)]
+    pub max_occurrences: Option<u32>,
+    /// Transactions generated so far, kept alongside `last_date`.
+    #[serde(default)]
+    pub occurrence_count: u32,
+    /// Moves generated transactions off weekends and holidays, e.g. rent due on the 1st that clears on the
+    /// next weekday. Occurrences are still calculated from the unadjusted dates so they don't drift.
+    #[serde(default)]
+    pub business_day_rule: BusinessDayAdjustment,
+    /// Dates besides weekends that aren't business days for the business day rule.
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_vec_naivedate")]
+    #[serde(deserialize_with = "deserialize_vec_naivedate")]
+    pub holidays: Vec<NaiveDate>,
 }
 
 impl Schedule {
     pub fn schedule_next(&mut self, max_date : NaiveDate) -> Option<Transaction> {
-        let next_date = self.get_next_date();
+        if !self.active || self.occurrences_exhausted() {
+            return None
+        }
+        let mut next_date = self.get_next_date();
+        while self.skip_dates.contains(&next_date) && self.within_limits(next_date, max_date) {
+            self.last_date = Some(next_date);
+            next_date = self.get_next_date();
+        }
 
-        if next_date <= max_date && (self.end_date.is_none() || next_date <= self.end_date.unwrap()) {
+        // The business day adjustment can move the posting date past the max date, e.g. the horizon, in
+        // which case the occurrence is left for a later run.
+        let posting_date = self.adjust_date(next_date);
+        if self.within_limits(next_date, max_date) && posting_date <= max_date {
             let transaction_id = Uuid::new_v4();
             let entries = self.entries.iter()
-                .map(|e| self.build_entry(transaction_id, next_date, e))
+                .map(|e| self.build_entry(transaction_id, posting_date, e))
                 .collect();
 
             let transaction = Transaction{
                 id: transaction_id,
                 entries: entries,
                 status: TransactionStatus::Projected,
-                schedule_id: Some(self.id)
+                source: TransactionSource::Schedule { schedule_id: self.id },
+                kind: TransactionKind::Normal,
+                approved: true,
+                related: Vec::new(),
             };
 
             self.last_date = Some(next_date);
+            self.occurrence_count += 1;
             return Some(transaction)
         }
 
         return None
     }
 
+    /// True if any entry, or the modifier, references the account.
+    pub fn involves_account(&self, account_id: &Uuid) -> bool {
+        self.entries.iter().any(|e| e.account_id == *account_id)
+            || self.modifier.as_ref().is_some_and(|m| match &m.kind {
+                ModifierKind::PercentOfAccountBalance { account_id: id, .. } => id == account_id,
+                ModifierKind::StraightLine { .. } => false,
+            })
+    }
+
+    /// Date the next transaction will actually post, after skipped dates and moving off days that aren't
+    /// business days in the calendar. None if the schedule is inactive or has ended.
+    pub fn effective_next_date(&self, calendar: &Calendar) -> Option<NaiveDate> {
+        self.next_occurrence(NaiveDate::MAX).map(|d| calendar.adjust(d))
+    }
+
+    /// As `effective_next_date`, but adjusted with the schedule's own business day rule and holidays.
+    pub fn next_posting_date(&self) -> Option<NaiveDate> {
+        self.next_occurrence(NaiveDate::MAX).map(|d| self.adjust_date(d))
+    }
+
+    /// Date of the next transaction this schedule will generate up to the max date, without generating it.
+    /// This is the unadjusted occurrence date, but None if the business day adjustment would post it after
+    /// the max date, as `schedule_next` won't generate it then either.
+    pub fn next_occurrence(&self, max_date: NaiveDate) -> Option<NaiveDate> {
+        if !self.active || self.occurrences_exhausted() {
+            return None
+        }
+        let mut next_date = self.get_next_date();
+        while self.skip_dates.contains(&next_date) && self.within_limits(next_date, max_date) {
+            next_date = self.next_date_after(Some(next_date));
+        }
+        if self.within_limits(next_date, max_date) && self.adjust_date(next_date) <= max_date {
+            return Some(next_date)
+        }
+        None
+    }
+
+    /// All occurrence dates from the start date up to the given date, excluding skipped dates.
+    pub fn occurrences_through(&self, max_date: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        let limit = self.max_occurrences.map_or(usize::MAX, |max| max as usize);
+        let mut date = self.next_date_after(None);
+        while self.within_limits(date, max_date) && dates.len() < limit {
+            if !self.skip_dates.contains(&date) {
+                dates.push(date);
+            }
+            date = self.next_date_after(Some(date));
+        }
+        dates
+    }
+
+    /// Date a transaction for the occurrence date is posted on, after any business day adjustment.
+    pub fn adjust_date(&self, date: NaiveDate) -> NaiveDate {
+        self.business_day_rule.adjust(date, &self.holidays)
+    }
+
+    /// True once the schedule has generated its maximum number of transactions.
+    pub fn occurrences_exhausted(&self) -> bool {
+        self.max_occurrences.is_some_and(|max| self.occurrence_count >= max)
+    }
+
+    /// Start generating again from the start date.
+    pub fn reset(&mut self) {
+        self.last_date = None;
+        self.occurrence_count = 0;
+    }
+
+    fn within_limits(&self, date: NaiveDate, max_date: NaiveDate) -> bool {
+        date <= max_date && (self.end_date.is_none() || date <= self.end_date.unwrap())
+    }
+
     fn build_entry(&self, transaction_id: Uuid, next_date: NaiveDate, entry: &ScheduleEntry) -> Entry {
-        Entry{
-            id: Uuid::new_v4(),
-            transaction_id: transaction_id,
-            description: entry.description.clone(),
-            amount: entry.amount.clone(),
-            account_id: entry.account_id,
-            entry_type: entry.entry_type,
-            date:        next_date.clone(),
-            balance:     None,
+        entry.to_entry(transaction_id, next_date)
+    }
+
+    /// Account ids this schedule debits and credits, as (debit accounts, credit accounts).
+    pub fn primary_accounts(&self) -> (Vec<Uuid>, Vec<Uuid>) {
+        let (debits, credits): (Vec<&ScheduleEntry>, Vec<&ScheduleEntry>) = self.entries
+            .iter()
+            .partition(|e| e.entry_type == Side::Debit);
+        (debits.iter().map(|e| e.account_id).collect(), credits.iter().map(|e| e.account_id).collect())
+    }
+
+    /// Reason this schedule can never generate another transaction, if that is the case.
+    pub fn inactive_reason(&self) -> Option<String> {
+        if !self.active {
+            return Some("Schedule is not active".to_string())
         }
+        if let Some(max) = self.max_occurrences.filter(|_| self.occurrences_exhausted()) {
+            return Some(format!("Reached the maximum of {} occurrences", max))
+        }
+        let end_date = self.end_date?;
+        if self.start_date > end_date {
+            return Some(format!("Start date {} is after end date {}", self.start_date, end_date))
+        }
+        let next_date = self.get_next_date();
+        if next_date > end_date {
+            return Some(format!("Next date {} is after end date {}", next_date, end_date))
+        }
+        None
     }
 
     pub fn get_next_date(&self) -> NaiveDate {
-        match self.last_date {
+        self.next_date_after(self.last_date)
+    }
+
+    fn next_date_after(&self, last_date: Option<NaiveDate>) -> NaiveDate {
+        match last_date {
            Some(d) => {
                 let last_date = d;
-                let mut new_date: NaiveDate;
-                match self.period {
-                    ScheduleEnum::Days => new_date = last_date.checked_add_signed(Duration::days(self.frequency)).unwrap(),
-                    ScheduleEnum::Weeks => new_date = last_date.checked_add_signed(Duration::days(self.frequency * 7)).unwrap(),
-                    ScheduleEnum::Months => new_date = shift_months(last_date, self.frequency.try_into().unwrap()),
-                    ScheduleEnum::Years => new_date = shift_years(last_date, self.frequency.try_into().unwrap()),
+                let mut new_date = self.period.shift(last_date, self.frequency);
+                let by_month = matches!(self.period, ScheduleEnum::Months | ScheduleEnum::Quarters | ScheduleEnum::Years);
+                if self.month_end_policy == MonthEndPolicy::Preserve && by_month {
+                    return self.next_date_with_start_day(last_date)
                 }
-                if (self.period == ScheduleEnum::Months || self.period == ScheduleEnum::Years) && new_date.day() < self.start_date.day() {
+                if by_month && new_date.day() < self.start_date.day() {
                     let new_month = new_date.month();
                     let mut result = new_date.checked_add_signed(Duration::days(1));
                     while result.is_some() && result.unwrap().month() == new_month {
@@ -270,6 +754,23 @@ impl Schedule {
         }
     }
 
+    /// The next monthly, quarterly or yearly date after the last date that falls on the start date's day.
+    fn next_date_with_start_day(&self, last_date: NaiveDate) -> NaiveDate {
+        let frequency: i32 = self.frequency.try_into().unwrap();
+        let mut shift = frequency;
+        loop {
+            let shifted = match self.period {
+                ScheduleEnum::Years => shift_years(last_date, shift),
+                ScheduleEnum::Quarters => shift_months(last_date, shift * 3),
+                _ => shift_months(last_date, shift),
+            };
+            if let Some(date) = shifted.with_day(self.start_date.day()) {
+                return date
+            }
+            shift += frequency;
+        }
+    }
+
 }
 
 
@@ -277,18 +778,24 @@ impl Schedule {
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashMap;
     use chrono::{NaiveDate};
     use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
     use uuid::Uuid;
 
+    use crate::calendar::{BusinessDayAdjustment, Calendar};
     use crate::account::ScheduleEnum;
+    use crate::account::MonthEndPolicy;
     use crate::account::Schedule;
     use crate::account::TransactionStatus;
+    use crate::account::TransactionKind;
+    use crate::account::TransactionSource;
 
     use super::Account;
     use super::Entry;
     use super::ScheduleEntry;
+    use super::StepSchedule;
     use super::Side;
     use super::Transaction;
 
@@ -322,7 +829,7 @@ mod tests {
         let account2 = Account::create_new("Loan 1", super::AccountType::Liability);
         let transaction_id = Uuid::new_v4();
         let date = NaiveDate::from_ymd(2023, 2, 14);
-        let mut t = Transaction{ id: transaction_id, entries: [].to_vec(), status: TransactionStatus::Recorded, schedule_id: None};
+        let mut t = Transaction{ id: transaction_id, entries: [].to_vec(), status: TransactionStatus::Recorded, source: TransactionSource::Manual, kind: TransactionKind::Normal, approved: true, related: Vec::new()};
         t.entries.push(build_entry(transaction_id, date, "loan payment", account1.id,Side::Credit, dec!(100)));
         t.entries.push(build_entry(transaction_id, date, "loan payment", account2.id, Side::Debit, dec!(100)));
 
@@ -337,6 +844,86 @@ mod tests {
         assert_eq!(dec!(300), t.entries.iter().find(|e| e.account_id == account2.id).unwrap().balance.unwrap());
     }
 
+    #[test]
+    fn test_find_entry_by_account() {
+        let account1 = Account::create_new("Savings Account 1", super::AccountType::Asset);
+        let account2 = Account::create_new("Loan 1", super::AccountType::Liability);
+        let transaction_id = Uuid::new_v4();
+        let date = NaiveDate::from_ymd(2023, 2, 14);
+        let mut t = Transaction{ id: transaction_id, entries: [].to_vec(), status: TransactionStatus::Recorded, source: TransactionSource::Manual, kind: TransactionKind::Normal, approved: true, related: Vec::new()};
+        t.entries.push(build_entry(transaction_id, date, "fee", account1.id, Side::Credit, dec!(5)));
+        t.entries.push(build_entry(transaction_id, date, "loan payment", account2.id, Side::Debit, dec!(105)));
+        t.entries.push(build_entry(transaction_id, date, "loan payment", account1.id, Side::Credit, dec!(100)));
+
+        let first = t.find_entry_by_account(&account1.id).unwrap();
+        assert_eq!(t.entries[0].id, first.id);
+        assert_eq!(dec!(5), first.amount);
+        assert_eq!(t.entries[1].id, t.find_entry_by_account(&account2.id).unwrap().id);
+        assert!(t.find_entry_by_account(&Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_balance_to() {
+        let transaction_id = Uuid::new_v4();
+        let date = NaiveDate::from_ymd(2023, 2, 14);
+        let income = Uuid::new_v4();
+        let accounts = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let mut t = Transaction{ id: transaction_id, entries: [].to_vec(), status: TransactionStatus::Recorded, source: TransactionSource::Manual, kind: TransactionKind::Normal, approved: true, related: Vec::new()};
+        t.entries.push(build_entry(transaction_id, date, "pay", income, Side::Credit, dec!(100.00)));
+        for account_id in accounts {
+            let share = (dec!(100.00) / dec!(3)).round_dp(2);
+            t.entries.push(build_entry(transaction_id, date, "pay", account_id, Side::Debit, share));
+        }
+        assert!(!t.is_balanced());
+
+        t.balance_to(accounts[2]);
+        assert!(t.is_balanced());
+        assert_eq!(dec!(33.33), t.entries[1].amount);
+        assert_eq!(dec!(33.33), t.entries[2].amount);
+        assert_eq!(dec!(33.34), t.entries[3].amount);
+
+        t.entries[0].amount = dec!(99.99);
+        t.balance_to(income);
+        assert_eq!(dec!(100.00), t.entries[0].amount);
+    }
+
+    #[test]
+    fn test_summary() {
+        let account1 = Account::create_new("Savings", super::AccountType::Asset);
+        let account2 = Account::create_new("Credit", super::AccountType::Liability);
+        let transaction_id = Uuid::new_v4();
+        let date = NaiveDate::from_ymd(2022, 6, 4);
+        let mut t = Transaction{ id: transaction_id, entries: [].to_vec(), status: TransactionStatus::Recorded, source: TransactionSource::Manual, kind: TransactionKind::Normal, approved: true, related: Vec::new()};
+        t.entries.push(build_entry(transaction_id, date, "received moneys", account1.id, Side::Debit, dec!(10000)));
+        t.entries.push(build_entry(transaction_id, date, "received moneys", account2.id, Side::Credit, dec!(10000)));
+        let accounts: HashMap<Uuid, Account> = [(account1.id, account1), (account2.id, account2)].into_iter().collect();
+        assert_eq!("2022-06-04: received moneys — Dr Savings 10000, Cr Credit 10000", t.summary(&accounts));
+
+        let other = Uuid::new_v4();
+        t.entries.push(build_entry(transaction_id, date, "received moneys", other, Side::Credit, dec!(5)));
+        assert_eq!(format!("2022-06-04: received moneys — Dr Savings 10000, Cr Credit 10000, Cr {} 5", other), t.summary(&accounts));
+    }
+
+    #[test]
+    fn test_external_entry() {
+        let entry = build_entry(Uuid::new_v4(), NaiveDate::from_ymd(2023, 2, 14), "loan payment", Uuid::new_v4(), Side::Credit, dec!(100));
+        let json: serde_json::Value = serde_json::to_value(entry.to_external()).unwrap();
+        assert_eq!("2023-02-14", json["transaction_date"]);
+        assert_eq!("loan payment", json["memo"]);
+        assert!(json.get("date").is_none());
+        assert!(json.get("description").is_none());
+
+        let native: serde_json::Value = serde_json::to_value(&entry).unwrap();
+        assert_eq!("loan payment", native["description"]);
+
+        let external: super::ExternalEntry = serde_json::from_value(json).unwrap();
+        let round_trip = Entry::from_external(external);
+        assert_eq!(entry.id, round_trip.id);
+        assert_eq!(entry.date, round_trip.date);
+        assert_eq!(entry.description, round_trip.description);
+        assert_eq!(entry.amount, round_trip.amount);
+    }
+
     fn build_entry(transaction_id: Uuid, date: NaiveDate, description: &str, account_id: Uuid, entry_type:Side,amount:Decimal) -> Entry {
         Entry{
             id: Uuid::new_v4(),
@@ -346,7 +933,12 @@ mod tests {
             account_id: account_id,
             entry_type,
             amount: amount,
-            balance: None
+            balance: None,
+            currency: None,
+            foreign_amount: None,
+            reconciled: false,
+            exclude_from_reports: false,
+            dimensions: HashMap::new(),
         }
     }
     #[test]
@@ -377,6 +969,15 @@ mod tests {
             start_date:   NaiveDate::from_ymd(2023, 1, 31),
             end_date:   None,
             last_date:   Some(NaiveDate::from_ymd(2023, 2, 28)),
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: None,
+            active: true,
+            notes: None,
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
             entries: Vec::new()
         };
 
@@ -387,6 +988,38 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_month_end_policies() {
+        let mut s = build_schedule(1, ScheduleEnum::Months);
+        s.start_date = NaiveDate::from_ymd(2023, 1, 31);
+        s.last_date = Some(NaiveDate::from_ymd(2023, 1, 31));
+        assert_eq!(NaiveDate::from_ymd(2023, 2, 28), s.get_next_date());
+        s.last_date = Some(NaiveDate::from_ymd(2023, 2, 28));
+        assert_eq!(NaiveDate::from_ymd(2023, 3, 31), s.get_next_date());
+        s.last_date = Some(NaiveDate::from_ymd(2023, 3, 31));
+        assert_eq!(NaiveDate::from_ymd(2023, 4, 30), s.get_next_date());
+
+        s.month_end_policy = MonthEndPolicy::Preserve;
+        s.last_date = Some(NaiveDate::from_ymd(2023, 1, 31));
+        assert_eq!(NaiveDate::from_ymd(2023, 3, 31), s.get_next_date());
+        s.last_date = Some(NaiveDate::from_ymd(2023, 3, 31));
+        assert_eq!(NaiveDate::from_ymd(2023, 5, 31), s.get_next_date());
+    }
+
+    #[test]
+    fn test_leap_day_yearly_preserve() {
+        let mut s = build_schedule(1, ScheduleEnum::Years);
+        s.month_end_policy = MonthEndPolicy::Preserve;
+        s.start_date = NaiveDate::from_ymd(2024, 2, 29);
+        s.last_date = Some(NaiveDate::from_ymd(2024, 2, 29));
+        assert_eq!(NaiveDate::from_ymd(2028, 2, 29), s.get_next_date());
+    }
+
+    #[test]
+    fn test_quarterly() {
+        test_get_next(ScheduleEnum::Quarters, 1, NaiveDate::from_ymd(2022, 6, 11))
+    }
+
     #[test]
     fn test_yearly() {
         test_get_next(ScheduleEnum::Years, 1, NaiveDate::from_ymd(2023, 3, 11))
@@ -409,6 +1042,151 @@ mod tests {
         assert!(last.is_none())
     }
 
+    #[test]
+    fn test_step_schedule() {
+        let mut s= build_schedule(6, ScheduleEnum::Months);
+        s.entries.iter_mut().for_each(|e| {
+            e.step_schedule.add_step(NaiveDate::from_ymd(2023, 1, 1), dec!(150));
+            e.step_schedule.add_step(NaiveDate::from_ymd(2024, 1, 1), dec!(200));
+        });
+        let max_date = NaiveDate::from_ymd(2023, 12, 31);
+
+        let before = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 9, 11), before.entries[0].date);
+        assert_eq!(dec!(100.99), before.entries[0].amount);
+        let after = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2023, 3, 11), after.entries[0].date);
+        assert_eq!(dec!(150), after.entries[0].amount);
+        assert_eq!(dec!(150), after.entries[1].amount);
+    }
+
+    #[test]
+    fn test_schedule_entry_to_entry() {
+        let s = build_schedule(1, ScheduleEnum::Months);
+        let schedule_entry = &s.entries[1];
+        let transaction_id = Uuid::new_v4();
+        let date = NaiveDate::from_ymd(2022, 4, 11);
+
+        let entry = schedule_entry.to_entry(transaction_id, date);
+        assert_eq!(transaction_id, entry.transaction_id);
+        assert_eq!(date, entry.date);
+        assert_eq!(schedule_entry.description, entry.description);
+        assert_eq!(schedule_entry.account_id, entry.account_id);
+        assert_eq!(schedule_entry.entry_type, entry.entry_type);
+        assert_eq!(schedule_entry.amount, entry.amount);
+        assert_eq!(None, entry.balance);
+        assert_eq!(None, entry.currency);
+        assert_eq!(None, entry.foreign_amount);
+        assert!(!entry.reconciled);
+        assert!(!entry.exclude_from_reports);
+        assert_ne!(entry.id, schedule_entry.to_entry(transaction_id, date).id);
+    }
+
+    #[test]
+    fn test_max_occurrences() {
+        let mut s = build_schedule(1, ScheduleEnum::Months);
+        s.reset();
+        s.max_occurrences = Some(3);
+        let max_date = NaiveDate::from_ymd(2030, 1, 1);
+        s.schedule_next(max_date).unwrap();
+        s.schedule_next(max_date).unwrap();
+
+        // The count is saved with the schedule, so a reloaded schedule carries on where it left off.
+        let mut s: Schedule = serde_json::from_str(&serde_json::to_string(&s).unwrap()).unwrap();
+        assert_eq!(2, s.occurrence_count);
+        assert_eq!(NaiveDate::from_ymd(2022, 5, 11), s.schedule_next(max_date).unwrap().entries[0].date);
+        assert!(s.schedule_next(max_date).is_none());
+        assert_eq!(None, s.next_occurrence(max_date));
+        assert_eq!(Some("Reached the maximum of 3 occurrences".to_string()), s.inactive_reason());
+        assert_eq!(3, s.occurrences_through(max_date).len());
+
+        s.reset();
+        s.end_date = Some(NaiveDate::from_ymd(2022, 4, 30));
+        assert!(s.schedule_next(max_date).is_some());
+        assert!(s.schedule_next(max_date).is_some());
+        assert!(s.schedule_next(max_date).is_none());
+        assert_eq!(2, s.occurrence_count);
+    }
+
+    #[test]
+    fn test_max_occurrences_missing_from_json() {
+        let mut json = serde_json::to_value(build_schedule(1, ScheduleEnum::Months)).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("max_occurrences");
+        fields.remove("occurrence_count");
+        let s: Schedule = serde_json::from_value(json).unwrap();
+        assert_eq!(None, s.max_occurrences);
+        assert_eq!(0, s.occurrence_count);
+    }
+
+    #[test]
+    fn test_business_day_adjustment() {
+        let mut s = build_schedule(1, ScheduleEnum::Months);
+        s.start_date = NaiveDate::from_ymd(2022, 5, 1);
+        s.reset();
+        s.business_day_rule = BusinessDayAdjustment::Following;
+        s.holidays.push(NaiveDate::from_ymd(2022, 6, 1));
+        let max_date = NaiveDate::from_ymd(2022, 7, 31);
+
+        let may = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 5, 2), may.entries[0].date);
+        assert_eq!(NaiveDate::from_ymd(2022, 5, 1), s.last_date.unwrap());
+        let june = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 2), june.entries[0].date);
+        let july = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 7, 1), july.entries[0].date);
+
+        // October 1st is a Saturday, posting on the 3rd would be past the max date.
+        let max_date = NaiveDate::from_ymd(2022, 10, 2);
+        assert_eq!(2, (0..3).filter_map(|_| s.schedule_next(max_date)).count());
+        assert_eq!(NaiveDate::from_ymd(2022, 9, 1), s.last_date.unwrap());
+        let october = s.schedule_next(NaiveDate::from_ymd(2022, 10, 3)).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 10, 3), october.entries[0].date);
+    }
+
+    #[test]
+    fn test_skip_dates() {
+        let mut s= build_schedule(1, ScheduleEnum::Months);
+        s.skip_dates.push(NaiveDate::from_ymd(2022, 5, 11));
+        let max_date = NaiveDate::from_ymd(2022, 6, 30);
+        let next = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 4, 11), next.entries[0].date);
+        let next = s.schedule_next(max_date).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 11), next.entries[0].date);
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 11), s.last_date.unwrap());
+        assert!(s.schedule_next(max_date).is_none());
+    }
+
+    #[test]
+    fn test_effective_next_date() {
+        let mut s= build_schedule(1, ScheduleEnum::Months);
+        s.skip_dates.push(NaiveDate::from_ymd(2022, 4, 11));
+        s.skip_dates.push(NaiveDate::from_ymd(2022, 5, 11));
+        let mut calendar = Calendar::new(BusinessDayAdjustment::Following);
+
+        assert_eq!(Some(NaiveDate::from_ymd(2022, 6, 13)), s.effective_next_date(&calendar));
+        calendar.adjustment = BusinessDayAdjustment::Preceding;
+        assert_eq!(Some(NaiveDate::from_ymd(2022, 6, 10)), s.effective_next_date(&calendar));
+        assert_eq!(Some(NaiveDate::from_ymd(2022, 6, 11)), s.next_posting_date());
+        s.business_day_rule = BusinessDayAdjustment::Following;
+        assert_eq!(Some(NaiveDate::from_ymd(2022, 6, 13)), s.next_posting_date());
+
+        s.end_date = Some(NaiveDate::from_ymd(2022, 5, 31));
+        assert_eq!(None, s.effective_next_date(&calendar));
+        assert_eq!(None, s.next_posting_date());
+        s.end_date = None;
+        s.active = false;
+        assert_eq!(None, s.effective_next_date(&calendar));
+    }
+
+    #[test]
+    fn test_skip_date_beyond_max_date() {
+        let mut s= build_schedule(1, ScheduleEnum::Months);
+        s.skip_dates.push(NaiveDate::from_ymd(2022, 4, 11));
+        assert!(s.schedule_next(NaiveDate::from_ymd(2022, 4, 1)).is_none());
+        assert_eq!(NaiveDate::from_ymd(2022, 3, 11), s.last_date.unwrap());
+    }
+
     #[test]
     fn test_past_max_date() {
         let mut s= build_schedule(3, ScheduleEnum::Months);
@@ -432,7 +1210,7 @@ mod tests {
         let max_date = NaiveDate::from_ymd(2022, 05, 11);
         let next = s.schedule_next(max_date).unwrap();
         assert_eq!(s.start_date, next.entries[0].date);
-        assert_eq!(s.id, next.schedule_id.unwrap());
+        assert_eq!(s.id, next.schedule_id().unwrap());
     }
 
     fn build_schedule(frequency: i64, period: ScheduleEnum) -> Schedule {
@@ -444,6 +1222,15 @@ mod tests {
             start_date:   NaiveDate::from_ymd(2022, 3, 11),
             end_date:   None,
             last_date:   Some(NaiveDate::from_ymd(2022, 3, 11)),
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: None,
+            active: true,
+            notes: None,
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
             entries: Vec::new()
             // amount:      dec!(100.99),
             // description: "stes1".to_string(),
@@ -456,6 +1243,7 @@ mod tests {
             account_id: Uuid::new_v4(),
             entry_type: Side::Debit,
             schedule_id: s.id,
+            step_schedule: StepSchedule::default(),
         });
         s.entries.push( ScheduleEntry {
             amount: dec!(100.99),
@@ -463,10 +1251,19 @@ mod tests {
             account_id: Uuid::new_v4(),
             entry_type: Side::Credit,
             schedule_id: s.id,
+            step_schedule: StepSchedule::default(),
         });
         return s
     }
 
+    #[test]
+    fn test_primary_accounts() {
+        let s = build_schedule(1, ScheduleEnum::Months);
+        let (debits, credits) = s.primary_accounts();
+        assert_eq!(vec![s.entries[0].account_id], debits);
+        assert_eq!(vec![s.entries[1].account_id], credits);
+    }
+
     fn test_get_next(period: ScheduleEnum, frequency: i64, expected_date: NaiveDate) {
         let s= build_schedule(frequency, period);
         let last_at_start = s.last_date;
@@ -1,28 +1,136 @@
 #![allow(dead_code)]
-use std::{path::Path, fs::File, io::Read};
-use std::{io};
+use std::{fmt, path::Path, fs::File, io::Read};
+use std::io::{self, BufRead, BufReader, Write};
 
+use chrono::NaiveDate;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::account::Transaction;
 use crate::books::{Books, BooksError};
 
 /// Simple JSON file storage for Books.
 
-pub fn load_books<P: AsRef<Path>>(path: P) -> Result<Books, io::Error> {
-    match File::open(path) {
-        Err(why) => println!("Open file failed : {:?}", why.kind()),
-        Ok(mut file) => {
-            let mut content: String = String::new();
-            file.read_to_string(&mut content)?;
-            match serde_json::from_str::<Books>(&mut content) {
-                Err(why) => println!("Parsing file json failed : {:?}", why),
-                Ok(books) => {
-                    return Ok(books)
-                }
-            }
+/// Why Books couldn't be loaded.
+#[derive(Debug)]
+pub enum RepoError {
+    /// There is no file at the path, e.g. the Books haven't been saved yet.
+    NotFound,
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file was read but isn't valid Books JSON.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepoError::NotFound => write!(f, "Books file not found"),
+            RepoError::Io(e) => write!(f, "Failed to read books file: {}", e),
+            RepoError::Json(e) => write!(f, "Failed to parse books file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RepoError {}
+
+impl From<io::Error> for RepoError {
+    fn from(e: io::Error) -> RepoError {
+        match e.kind() {
+            io::ErrorKind::NotFound => RepoError::NotFound,
+            _ => RepoError::Io(e),
         }
     }
+}
 
-    // There was no file, or the file failed to load, create new Books.
-    Ok(Books::build_empty("My Books"))
+impl From<serde_json::Error> for RepoError {
+    fn from(e: serde_json::Error) -> RepoError {
+        RepoError::Json(e)
+    }
+}
+
+/// Load Books from a JSON file. A missing file is `RepoError::NotFound`, so callers can start new Books,
+/// while a file that can't be read or parsed is an error that shouldn't be overwritten.
+pub fn load_books<P: AsRef<Path>>(path: P) -> Result<Books, RepoError> {
+    let mut content: String = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+    Ok(serde_json::from_str::<Books>(&content)?)
+}
+
+/// Load Books keeping only the transactions dated on or after the given date. Transactions are read one
+/// at a time and older ones dropped as they are read, so the full list is never held in memory. Errors are
+/// reported as for `load_books`.
+pub fn load_books_since<P: AsRef<Path>>(path: P, since: NaiveDate) -> Result<Books, RepoError> {
+    let file = BufReader::new(File::open(path)?);
+    let mut deserializer = serde_json::Deserializer::from_reader(file);
+    let books_value = BooksSince { since }.deserialize(&mut deserializer)?;
+    Ok(serde_json::from_value::<Books>(books_value)?)
+}
+
+/// Reads the Books JSON object into a `Value`, filtering the transactions as they are read.
+struct BooksSince {
+    since: NaiveDate,
+}
+
+impl<'de> DeserializeSeed<'de> for BooksSince {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BooksSince {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a Books object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut fields = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = if key == "transactions" {
+                map.next_value_seed(TransactionsSince { since: self.since })?
+            } else {
+                map.next_value::<Value>()?
+            };
+            fields.insert(key, value);
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+struct TransactionsSince {
+    since: NaiveDate,
+}
+
+impl<'de> DeserializeSeed<'de> for TransactionsSince {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TransactionsSince {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a list of transactions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        use serde::de::Error;
+        let mut transactions = Vec::new();
+        while let Some(transaction) = seq.next_element::<Transaction>()? {
+            if transaction.date().is_some_and(|d| d < self.since) {
+                continue;
+            }
+            transactions.push(serde_json::to_value(transaction).map_err(A::Error::custom)?);
+        }
+        Ok(Value::Array(transactions))
+    }
 }
 
 pub fn save_books<P: AsRef<Path>>(path: P, books: &Books) -> io::Result<()> {
@@ -30,6 +138,16 @@ pub fn save_books<P: AsRef<Path>>(path: P, books: &Books) -> io::Result<()> {
     Ok(())
 }
 
+/// Save the Books with every amount at the given number of decimal places, e.g. 2 so the JSON shows
+/// "100.00" rather than "100". The Books themselves keep their precision. Nothing is saved if an amount has
+/// more decimal places, as it would be lost when the file is loaded.
+pub fn save_books_at_scale<P: AsRef<Path>>(path: P, books: &Books, scale: u32) -> io::Result<()> {
+    let mut books = books.clone();
+    books.rescale_amounts(scale)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.error))?;
+    save_books(path, &books)
+}
+
 pub fn new_books<P: AsRef<Path>>(path: P, books: &Books) ->  Result<(), BooksError>{
     let file_result = &File::options()
             .write(true)
@@ -49,26 +167,62 @@ pub fn new_books<P: AsRef<Path>>(path: P, books: &Books) ->  Result<(), BooksErr
 
 }
 
+/// Append a transaction to a newline delimited JSON transaction log.
+pub fn append_transaction_log<P: AsRef<Path>>(path: P, transaction: &Transaction) -> io::Result<()> {
+    let mut file = File::options().create(true).append(true).open(path)?;
+    serde_json::to_writer(&mut file, transaction)?;
+    file.write_all(b"\n")
+}
+
+/// Load the Books from the last full snapshot then replay the transactions logged since, skipping any
+/// transactions already in the snapshot. Without a snapshot the log is replayed into new Books.
+pub fn recover<P: AsRef<Path>, L: AsRef<Path>>(snapshot_path: P, log_path: L) -> Result<Books, RepoError> {
+    let mut books = match load_books(snapshot_path) {
+        Err(RepoError::NotFound) => Books::build_empty("My Books"),
+        result => result?,
+    };
+    let log = match File::open(log_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(books),
+        Err(e) => return Err(RepoError::Io(e)),
+    };
+
+    for line in BufReader::new(log).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let transaction = serde_json::from_str::<Transaction>(&line)?;
+        if books.transaction(transaction.id).is_some() {
+            continue;
+        }
+        books.add_transaction(transaction)
+            .map_err(|e| RepoError::Io(io::Error::new(io::ErrorKind::InvalidData, e.error)))?;
+    }
+    Ok(books)
+}
+
 #[cfg(test)]
 
 mod tests {
+    use std::collections::HashMap;
     use std::fs::File;
     use std::io::prelude::*;
     use rust_decimal::Decimal;
     use uuid::Uuid;
     use chrono::{NaiveDate};
     use rust_decimal_macros::dec;
-    use crate::{account::{Account, Transaction, Side, TransactionStatus, Schedule, ScheduleEnum, Entry, AccountType, ScheduleEntry}, book_repo::save_books};
-    use super::{Books, load_books};
+    use crate::{account::{Account, Transaction, Side, TransactionStatus, TransactionKind, TransactionSource, Schedule, ScheduleEnum, MonthEndPolicy, Entry, AccountType, ScheduleEntry, StepSchedule}, book_repo::save_books, calendar::BusinessDayAdjustment};
+    use super::{Books, load_books, load_books_since, append_transaction_log, recover, save_books_at_scale, RepoError};
 
    fn build_books() -> Books {
         let mut books = Books::build_empty("My Books");
         let dr_account1 = Account::create_new("Savings Account 1", AccountType::Asset);
         let id1: Uuid = dr_account1.id;
-        books.add_account(dr_account1);
+        books.add_account(dr_account1).unwrap();
         let cr_account1 = Account::create_new("Credit Account 1", AccountType::Liability);
         let id2: Uuid = cr_account1.id;
-        books.add_account(cr_account1);
+        books.add_account(cr_account1).unwrap();
         let date = NaiveDate::from_ymd(2022, 6, 4);
         let t1 = build_transaction(id1, id2, "received moneys", date, dec!(10000));
         books.add_transaction(t1).unwrap();
@@ -85,6 +239,15 @@ mod tests {
             start_date: NaiveDate::from_ymd(2022, 6, 4),
             end_date: None,
             last_date: Some(NaiveDate::from_ymd(2022, 6, 4)),
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: None,
+            active: true,
+            notes: Some("Cancel after the lease ends July 2025".to_string()),
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
             entries: vec![
                 ScheduleEntry {
                     amount: dec!(200),
@@ -92,6 +255,7 @@ mod tests {
                     account_id: id1,
                     entry_type: Side::Debit,
                     schedule_id: s_id_1,
+                    step_schedule: StepSchedule::default(),
                 },
                 ScheduleEntry {
                     amount: dec!(200),
@@ -99,6 +263,7 @@ mod tests {
                     account_id: id2,
                     entry_type: Side::Credit,
                     schedule_id: s_id_1,
+                    step_schedule: StepSchedule::default(),
                 }
             ]
         };
@@ -113,12 +278,15 @@ mod tests {
                 id: transaction_id,
                 entries: vec![
                     Entry{id:Uuid::new_v4(),transaction_id,date,description:description.to_string(),account_id:dr_account_id,entry_type:Side::Debit,
-                        amount,balance:None },
+                        amount,balance:None,currency:None,foreign_amount:None,reconciled:false,exclude_from_reports:false,dimensions:HashMap::new() },
                     Entry{id:Uuid::new_v4(),transaction_id,date,description:description.to_string(),account_id:cr_account_id,entry_type:Side::Credit,
-                        amount,balance:None},
+                        amount,balance:None,currency:None,foreign_amount:None,reconciled:false,exclude_from_reports:false,dimensions:HashMap::new()},
                 ],
                 status: TransactionStatus::Recorded,
-                schedule_id: None
+                source: TransactionSource::Manual,
+                kind: TransactionKind::Normal,
+                approved: true,
+                related: Vec::new(),
             };
         t1
     }
@@ -148,4 +316,162 @@ mod tests {
         let result = load_books(filepath);
         assert_eq!(books.accounts().len(), result.unwrap().accounts().len());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_load_books_errors() {
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        assert!(matches!(load_books(&path), Err(RepoError::NotFound)));
+
+        std::fs::write(&path, "{\"name\": \"My Books\", \"accounts\": ").unwrap();
+        let result = load_books(&path);
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(e @ RepoError::Json(_)) => assert!(e.to_string().starts_with("Failed to parse books file")),
+            _ => panic!("expected a JSON error"),
+        }
+    }
+
+    #[test]
+    fn test_recover() {
+        let books = build_books();
+        let dir = std::env::temp_dir();
+        let snapshot_path = dir.join(format!("{}.json", Uuid::new_v4()));
+        let log_path = dir.join(format!("{}.log", Uuid::new_v4()));
+        save_books(&snapshot_path, &books).unwrap();
+
+        let accounts = books.accounts();
+        let new_transaction = build_transaction(accounts[0].id, accounts[1].id, "logged", NaiveDate::from_ymd(2022, 6, 6), dec!(5));
+        append_transaction_log(&log_path, &books.transactions()[0]).unwrap();
+        append_transaction_log(&log_path, &new_transaction).unwrap();
+
+        let recovered = recover(&snapshot_path, &log_path).unwrap();
+        std::fs::remove_file(&snapshot_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+
+        assert_eq!(books.transactions().len() + 1, recovered.transactions().len());
+        assert_eq!(1, recovered.transactions().iter().filter(|t| t.id == books.transactions()[0].id).count());
+        assert!(recovered.transaction(new_transaction.id).is_some());
+    }
+
+    #[test]
+    fn test_load_books_since() {
+        let books = build_books();
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        save_books(&path, &books).unwrap();
+
+        let loaded = load_books_since(&path, NaiveDate::from_ymd(2022, 6, 5)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(1, loaded.transactions().len());
+        assert_eq!("Gave some moneys back", loaded.transactions()[0].entries[0].description);
+        assert_eq!(books.accounts().len(), loaded.accounts().len());
+        assert_eq!(books.schedules().len(), loaded.schedules().len());
+        assert_eq!(books.id, loaded.id);
+
+        assert!(matches!(load_books_since(&path, NaiveDate::from_ymd(2022, 6, 5)), Err(RepoError::NotFound)));
+        std::fs::write(&path, "{\"transactions\": [").unwrap();
+        let result = load_books_since(&path, NaiveDate::from_ymd(2022, 6, 5));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(RepoError::Json(_))));
+    }
+
+    #[test]
+    #[cfg(not(feature = "integer_amounts"))]
+    fn test_save_books_at_scale() {
+        let mut books = build_books();
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        save_books_at_scale(&path, &books, 2).unwrap();
+        let mut content = String::new();
+        File::open(&path).unwrap().read_to_string(&mut content).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!("10000.00", json["transactions"][0]["entries"][0]["amount"]);
+        assert_eq!("98.99", json["transactions"][1]["entries"][0]["amount"]);
+        assert_eq!("200.00", json["scheduler"]["schedules"][0]["entries"][0]["amount"]);
+        assert_eq!("0.00", json["accounts"][books.accounts()[0].id.to_string()]["starting_balance"]);
+        assert_eq!("10000", books.transactions()[0].entries[0].amount.to_string());
+
+        let mut dinars = Account::create_new("Dinar Account", AccountType::Asset);
+        dinars.currency = Some("BHD".to_string());
+        dinars.starting_balance = dec!(12.345);
+        books.add_account(dinars).unwrap();
+        let error = save_books_at_scale(&path, &books, 2).err().unwrap();
+        assert_eq!("Amount 12.345 has more than 2 decimal places", error.to_string());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_schedule_notes() {
+        let books = build_books();
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        save_books(&path, &books).unwrap();
+
+        let loaded = load_books(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(Some("Cancel after the lease ends July 2025".to_string()), loaded.schedules()[0].notes);
+    }
+
+    #[test]
+    #[cfg(feature = "integer_amounts")]
+    fn test_minor_units_storage() {
+        let mut books = build_books();
+        let mut dinars = Account::create_new("Dinar Account", AccountType::Asset);
+        dinars.currency = Some("BHD".to_string());
+        dinars.starting_balance = dec!(12.345);
+        let dinars_id = dinars.id;
+        books.add_account(dinars).unwrap();
+        let mut transaction = books.transactions()[1].clone();
+        transaction.entries[0].currency = Some("JPY".to_string());
+        transaction.entries[0].foreign_amount = Some(dec!(15000));
+        books.update_transaction(transaction).unwrap();
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        save_books(&path, &books).unwrap();
+        let mut content = String::new();
+        File::open(&path).unwrap().read_to_string(&mut content).unwrap();
+        let loaded = load_books(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(9899, json["transactions"][1]["entries"][0]["amount"]);
+        assert_eq!(2, json["transactions"][1]["entries"][0]["exponent"]);
+        assert_eq!(1500000, json["transactions"][1]["entries"][0]["foreign_amount"]);
+        assert_eq!(Some(dec!(15000)), loaded.transactions()[1].entries[0].foreign_amount);
+        assert_eq!(12345, json["accounts"][dinars_id.to_string()]["starting_balance"]);
+        assert_eq!(3, json["accounts"][dinars_id.to_string()]["exponent"]);
+        let date = NaiveDate::from_ymd(2022, 12, 31);
+        assert_eq!(books.balances_as_of(date), loaded.balances_as_of(date));
+    }
+
+    #[test]
+    fn test_saved_reports_match() {
+        let mut books = Books::build_empty("My Books");
+        let date = NaiveDate::from_ymd(2022, 6, 4);
+        for (currency, starting_balance, amount) in [("USD", dec!(1000.50), dec!(98.99)), ("JPY", dec!(150000), dec!(1500)),
+                                                     ("BHD", dec!(12.345), dec!(1.005)), ("EUR", dec!(0), dec!(3.3333))] {
+            let mut bank = Account::create_new(&format!("{} Bank", currency), AccountType::Asset);
+            bank.currency = Some(currency.to_string());
+            bank.starting_balance = starting_balance;
+            let mut sales = Account::create_new(&format!("{} Sales", currency), AccountType::Revenue);
+            sales.currency = Some(currency.to_string());
+            let (bank_id, sales_id) = (bank.id, sales.id);
+            books.add_account(bank).unwrap();
+            books.add_account(sales).unwrap();
+            books.add_transaction(build_transaction(bank_id, sales_id, "Sale", date, amount)).unwrap();
+        }
+        books.recompute_entry_balances();
+        let path = std::env::temp_dir().join(format!("{}.json", Uuid::new_v4()));
+        save_books(&path, &books).unwrap();
+        let loaded = load_books(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let rows = |b: &Books| b.trial_balance(date).into_iter()
+            .map(|r| (r.account_id, r.debit_total, r.credit_total, r.balance))
+            .collect::<Vec<(Uuid, Decimal, Decimal, Decimal)>>();
+        assert_eq!(rows(&books), rows(&loaded));
+        assert_eq!(books.balances_as_of(date), loaded.balances_as_of(date));
+        let balances = |b: &Books| b.transactions().iter().flat_map(|t| t.entries.iter().map(|e| e.balance)).collect::<Vec<Option<Decimal>>>();
+        assert_eq!(balances(&books), balances(&loaded));
+    }
+}
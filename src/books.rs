@@ -1,33 +1,330 @@
-use std::{collections::HashMap, cmp::Ordering};
-use chrono::{NaiveDate};
+use std::{collections::{HashMap, BTreeMap}, cmp::Ordering, cell::OnceCell, io::Write};
+use chrono::{Datelike, NaiveDate};
+use chronoutil::shift_months;
+use rust_decimal::Decimal;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
-use crate::{account::{Account, Schedule, Transaction, Entry}, scheduler::{Scheduler}};
+use rust_decimal_macros::dec;
+use crate::{account::{Account, AccountCategory, AccountType, Side, Schedule, ScheduleEntry, ScheduleEnum, StepSchedule, MonthEndPolicy, Modifier, ModifierKind, Transaction, TransactionKind, TransactionSource, TransactionStatus, Entry}, scheduler::{Scheduler}};
+use crate::calendar::BusinessDayAdjustment;
+use crate::csv_import::{csv_field, csv_fields};
+use crate::format::format_accounting;
+use crate::minor_units::{exponent, DEFAULT_EXPONENT};
+use crate::reconciliation::ReconciliationSession;
+use crate::serializer::{serialize_naivedate, deserialize_naivedate};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq  )]
+/// Entry dimension holding a transaction's external reference, e.g. a bank reference or cheque number.
+pub const REFERENCE_DIMENSION: &str = "reference";
+
+/// Name of the equity account `Books::roll_forward` carries net income into.
+pub const RETAINED_EARNINGS: &str = "Retained Earnings";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Settings {
     pub require_double_entry: bool,
+    #[serde(default)]
+    pub require_descriptions: bool,
+    #[serde(default)]
+    pub max_description_length: Option<usize>,
+    #[serde(default)]
+    pub rounding: RoundingStrategy,
+    /// Leave unapproved transactions out of balances until they are approved.
+    #[serde(default)]
+    pub require_approval: bool,
+    /// Prevent new accounts being added, i.e. freeze the chart of accounts.
+    #[serde(default)]
+    pub accounts_locked: bool,
+    /// ISO currency code given to accounts created through the books, blank for none.
+    #[serde(default)]
+    pub default_currency: String,
+    /// Allow transactions whose debits don't equal their credits even when double entry is required.
+    #[serde(default)]
+    pub allow_unbalanced: bool,
+    /// How far ahead, in years, schedules may be projected past today or the latest recorded transaction.
+    #[serde(default = "default_max_projection_years")]
+    pub max_projection_years: u32,
+}
+
+fn default_max_projection_years() -> u32 {
+    5
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            require_double_entry: false,
+            require_descriptions: false,
+            max_description_length: None,
+            rounding: RoundingStrategy::default(),
+            require_approval: false,
+            accounts_locked: false,
+            default_currency: String::new(),
+            allow_unbalanced: false,
+            max_projection_years: default_max_projection_years(),
+        }
+    }
+}
+
+impl Settings {
+    /// Round a calculated amount to cents using the book's rounding strategy.
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(2, self.rounding.strategy())
+    }
+
+    /// Round a calculated amount to the currency's decimal places using the book's rounding strategy, to
+    /// cents if there is no currency or it is unknown.
+    pub fn round_for_currency(&self, amount: Decimal, currency: Option<&str>) -> Decimal {
+        let places = currency.and_then(|c| exponent(c).ok()).unwrap_or(DEFAULT_EXPONENT);
+        amount.round_dp_with_strategy(places, self.rounding.strategy())
+    }
+}
+
+/// How calculated amounts, e.g. interest or currency conversions, are rounded.
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub enum RoundingStrategy {
+    /// Banker's rounding, halves go to the nearest even digit.
+    #[default]
+    HalfEven,
+    /// Halves go away from zero.
+    HalfUp,
+    /// Halves go towards zero.
+    HalfDown,
+    /// Always away from zero.
+    Up,
+    /// Always towards zero, i.e. truncate.
+    Down,
+}
+
+impl RoundingStrategy {
+    fn strategy(&self) -> rust_decimal::RoundingStrategy {
+        match self {
+            Self::HalfEven => rust_decimal::RoundingStrategy::MidpointNearestEven,
+            Self::HalfUp => rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+            Self::HalfDown => rust_decimal::RoundingStrategy::MidpointTowardZero,
+            Self::Up => rust_decimal::RoundingStrategy::AwayFromZero,
+            Self::Down => rust_decimal::RoundingStrategy::ToZero,
+        }
+    }
 }
 
 /// Book of accounts a.k.a The Books.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Books {
     pub id: Uuid,
     pub name: String,
     pub version: String,
     accounts: HashMap<Uuid, Account>,
     scheduler: Scheduler,
+    #[serde(deserialize_with = "deserialize_sorted_transactions")]
     transactions: Vec<Transaction>,
     pub settings: Settings,
+    /// Positions of transactions keyed by date, built on first use then updated as transactions are added,
+    /// updated and deleted.
+    #[serde(skip)]
+    date_index: OnceCell<BTreeMap<NaiveDate, Vec<usize>>>,
 }
 
 impl Books {
-    pub fn generate(&mut self, end_date: NaiveDate) {
-        self.transactions.append(&mut self.scheduler.generate(end_date));
-        self.transactions.sort_by(|a, b| a.entries[0].date.cmp(&b.entries[0].date));
+    /// Generate projected transactions from the schedules up to the end date. Transactions are generated
+    /// in date order so amounts based on account balances include earlier generated transactions.
+    /// Schedules referencing accounts that don't exist are skipped and returned with the reason. An end date
+    /// after the projection horizon, see `projection_horizon`, is rejected and nothing is generated.
+    pub fn generate(&mut self, end_date: NaiveDate, today: NaiveDate) -> Result<GenerateReport, BooksError> {
+        let horizon = self.projection_horizon(today);
+        if end_date > horizon {
+            return Err(BooksError::from_str(format!("End date {} is beyond the max_projection_years horizon of {}.", end_date, horizon).as_str()))
+        }
+        let skipped: Vec<(Uuid, String)> = self.scheduler.schedules()
+            .iter()
+            .filter_map(|s| self.missing_schedule_account(s).map(|id| (s.id, format!("Account {} not found", id))))
+            .collect();
+        let excluded: Vec<Uuid> = skipped.iter().map(|(id, _)| *id).collect();
+
+        while let Some(mut transaction) = self.scheduler.generate_next_excluding(end_date, &excluded) {
+            self.apply_modifier(&mut transaction);
+            self.insert_sorted(transaction);
+        }
+        Ok(GenerateReport { skipped })
+    }
+
+    /// Latest date schedules may be generated to, counted from today or the latest recorded transaction,
+    /// whichever is later.
+    pub fn projection_horizon(&self, today: NaiveDate) -> NaiveDate {
+        let latest_recorded = self.transactions
+            .iter()
+            .filter(|t| t.status == TransactionStatus::Recorded)
+            .filter_map(|t| t.date())
+            .max();
+        let base = latest_recorded.map_or(today, |d| d.max(today));
+        shift_months(base, 12 * self.settings.max_projection_years as i32)
+    }
+
+    /// The first account referenced by the schedule's entries or modifier that doesn't exist.
+    fn missing_schedule_account(&self, schedule: &Schedule) -> Option<Uuid> {
+        let modifier_account = schedule.modifier.as_ref().and_then(|m| match &m.kind {
+            ModifierKind::PercentOfAccountBalance { account_id, .. } => Some(*account_id),
+            ModifierKind::StraightLine { .. } => None,
+        });
+        schedule.entries
+            .iter()
+            .map(|e| e.account_id)
+            .chain(modifier_account)
+            .find(|id| !self.accounts.contains_key(id))
+    }
+
+    /// Remove all projected transactions and reset the schedules to generate again from their start dates.
+    /// Recorded transactions are untouched.
+    pub fn clear_projections(&mut self) {
+        self.transactions.retain(|t| t.status != TransactionStatus::Projected);
+        self.date_index.take();
+        self.scheduler.reset();
+    }
+
+    fn apply_modifier(&self, transaction: &mut Transaction) {
+        let schedule = match transaction.schedule_id()
+            .and_then(|id| self.scheduler.schedules().iter().find(|s| s.id == id)) {
+            Some(s) => s,
+            None => return,
+        };
+        let modifier = match schedule.modifier.as_ref() {
+            Some(m) => m,
+            None => return,
+        };
+        let date = match transaction.date() {
+            Some(d) => d,
+            None => return,
+        };
+        let modifier_date = match modifier.modifier_date_for(date) {
+            Some(d) => d,
+            None => return,
+        };
+
+        let amount = match &modifier.kind {
+            ModifierKind::PercentOfAccountBalance { account_id, percentage } => {
+                let balance_date = modifier_date.pred_opt().unwrap_or(modifier_date);
+                let balance = self.balance_as_of(*account_id, balance_date).unwrap_or(Decimal::ZERO);
+                self.settings.round(balance * percentage / Decimal::ONE_HUNDRED)
+            }
+            ModifierKind::StraightLine { total, periods } => {
+                let share = self.settings.round(total / Decimal::from(*periods));
+                let occurrence = schedule.occurrences_through(date).len() as u32;
+                if occurrence >= *periods {
+                    total - share * Decimal::from(periods - 1)
+                } else {
+                    share
+                }
+            }
+        };
+        transaction.entries.iter_mut().for_each(|e| e.amount = amount);
+    }
+
+    /// Create a schedule accruing interest on an account, e.g. a savings account or loan. Each period the
+    /// principal account's balance is multiplied by the periodic rate (the annual rate, as a percentage,
+    /// divided by the number of periods in a year) and posted between the principal and interest accounts.
+    pub fn create_interest_schedule(&mut self, principal_account: Uuid, interest_account: Uuid, annual_rate: Decimal, period: ScheduleEnum, start: NaiveDate) -> Result<Uuid, BooksError> {
+        let principal_side = match self.accounts.get(&principal_account) {
+            Some(a) => a.normal_balance(),
+            None => return Err(BooksError::from_str(format!("Invalid account: {}", principal_account).as_str())),
+        };
+        let periods_per_year = match period {
+            ScheduleEnum::Days => dec!(365),
+            ScheduleEnum::Weeks => dec!(52),
+            ScheduleEnum::Months => dec!(12),
+            ScheduleEnum::Quarters => dec!(4),
+            ScheduleEnum::Years => dec!(1),
+        };
+
+        let schedule_id = Uuid::new_v4();
+        let entry = |account_id, entry_type| ScheduleEntry {
+            schedule_id,
+            step_schedule: StepSchedule::default(),
+            description: "Interest".to_string(),
+            account_id,
+            entry_type,
+            amount: Decimal::ZERO,
+        };
+        let schedule = Schedule {
+            id: schedule_id,
+            name: "Interest".to_string(),
+            period: period.clone(),
+            frequency: 1,
+            start_date: start,
+            end_date: None,
+            last_date: None,
+            entries: vec![entry(principal_account, principal_side), entry(interest_account, principal_side.opposite())],
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: Some(Modifier {
+                kind: ModifierKind::PercentOfAccountBalance { account_id: principal_account, percentage: annual_rate / periods_per_year },
+                start_date: start,
+                period,
+                frequency: 1,
+            }),
+            active: true,
+            notes: None,
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
+        };
+        self.add_schedule(schedule)?;
+        Ok(schedule_id)
+    }
+
+    /// Create a monthly schedule depreciating a fixed asset over its useful life. Cost less salvage value
+    /// is posted in equal monthly amounts, debiting the expense account and crediting the accumulated
+    /// depreciation account, with the last month absorbing any rounding.
+    pub fn create_straight_line_depreciation(&mut self, asset: Uuid, accumulated: Uuid, expense: Uuid, cost: Decimal, salvage: Decimal, years: u32, start: NaiveDate) -> Result<Uuid, BooksError> {
+        if !self.valid_account_id(Some(asset)) {
+            return Err(BooksError::from_str(format!("Invalid account: {}", asset).as_str()))
+        }
+        if years < 1 {
+            return Err(BooksError::from_str("Useful life must be at least 1 year"))
+        }
+        if salvage > cost {
+            return Err(BooksError::from_str("Salvage value must not exceed cost"))
+        }
+        let periods = years * 12;
+        let total = cost - salvage;
+
+        let schedule_id = Uuid::new_v4();
+        let entry = |account_id, entry_type| ScheduleEntry {
+            schedule_id,
+            step_schedule: StepSchedule::default(),
+            description: "Depreciation".to_string(),
+            account_id,
+            entry_type,
+            amount: self.settings.round(total / Decimal::from(periods)),
+        };
+        let schedule = Schedule {
+            id: schedule_id,
+            name: "Depreciation".to_string(),
+            period: ScheduleEnum::Months,
+            frequency: 1,
+            start_date: start,
+            end_date: Some(ScheduleEnum::Months.shift(start, (periods - 1) as i64)),
+            last_date: None,
+            entries: vec![entry(expense, Side::Debit), entry(accumulated, Side::Credit)],
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: Some(Modifier {
+                kind: ModifierKind::StraightLine { total, periods },
+                start_date: start,
+                period: ScheduleEnum::Months,
+                frequency: 1,
+            }),
+            active: true,
+            notes: None,
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
+        };
+        self.add_schedule(schedule)?;
+        Ok(schedule_id)
     }
 }
 
@@ -39,12 +336,202 @@ impl Books {
             version: VERSION.to_string(),
             accounts: HashMap::new(),
             scheduler: Scheduler::build_empty(), transactions: Vec::new(),
-            settings: Settings{ require_double_entry: false },
+            settings: Settings::default(),
+            date_index: OnceCell::new(),
+        }
+    }
+
+    /// Start new books from these as of the given date, e.g. at year end. Asset, liability and equity
+    /// accounts start with their balance as of the date, revenue and expense accounts start at zero with
+    /// their net income added to the "Retained Earnings" equity account, so the new books balance. That
+    /// account is created in the new books if there isn't one. The accounts, schedules and settings are
+    /// copied, transactions are not. Schedules carry on from their last occurrence on or before the date.
+    pub fn roll_forward(&self, as_of: NaiveDate, new_name: &str) -> Books {
+        let rows = self.trial_balance(as_of);
+        let net_income: Decimal = rows.iter()
+            .map(|r| match r.account_type {
+                AccountType::Revenue => r.balance,
+                AccountType::Expense => -r.balance,
+                _ => Decimal::ZERO,
+            })
+            .sum();
+
+        let mut books = Books::build_empty(new_name);
+        books.settings = self.settings.clone();
+        books.scheduler = self.scheduler.clone();
+        for schedule in books.scheduler.schedules_mut() {
+            let occurrences = schedule.occurrences_through(as_of);
+            schedule.last_date = occurrences.last().copied();
+            schedule.occurrence_count = occurrences.len() as u32;
+        }
+        for row in rows {
+            let mut account = self.accounts[&row.account_id].clone();
+            account.starting_balance = if account.account_type.is_income_statement() {
+                Decimal::ZERO
+            } else {
+                row.balance
+            };
+            account.starting_balance_date = None;
+            account.balance = account.starting_balance;
+            books.accounts.insert(account.id, account);
         }
+
+        if !net_income.is_zero() {
+            let existing = books.accounts.values().find(|a| a.account_type == AccountType::Equity && a.name == RETAINED_EARNINGS).map(|a| a.id);
+            let retained_id = match existing {
+                Some(id) => id,
+                None => {
+                    let mut account = Account::create_new(RETAINED_EARNINGS, AccountType::Equity);
+                    account.currency = Some(self.settings.default_currency.clone()).filter(|c| !c.is_empty());
+                    let id = account.id;
+                    books.accounts.insert(id, account);
+                    id
+                },
+            };
+            let account = books.accounts.get_mut(&retained_id).unwrap();
+            account.starting_balance += net_income;
+            account.balance = account.starting_balance;
+        }
+        books
     }
 
-    pub fn add_account(&mut self, account: Account) {
+    /// Add an account, or replace the account with the same id. New accounts can't be added while the
+    /// accounts are locked, existing ones can still be edited.
+    pub fn add_account(&mut self, account: Account) -> Result<(), BooksError> {
+        if self.settings.accounts_locked && !self.accounts.contains_key(&account.id) {
+            return Err(BooksError::from_str("Accounts are locked, unlock them to add new accounts."))
+        }
+        if let Some(code) = &account.code {
+            self.check_code_unused(code, account.id)?;
+        }
+        if let Some(category) = &account.category {
+            Books::check_category(&account, category)?;
+        }
         self.accounts.insert (account.id, account);
+        Ok(())
+    }
+
+    /// Set or clear the category an account is grouped under.
+    pub fn set_account_category(&mut self, id: &Uuid, category: Option<AccountCategory>) -> Result<(), BooksError> {
+        let account = match self.accounts.get_mut(id) {
+            Some(a) => a,
+            None => return Err(BooksError::from_str(format!("Account {} not found.", id).as_str())),
+        };
+        if let Some(category) = &category {
+            Books::check_category(account, category)?;
+        }
+        account.category = category;
+        Ok(())
+    }
+
+    fn check_category(account: &Account, category: &AccountCategory) -> Result<(), BooksError> {
+        if category.normal_balance != account.normal_balance() {
+            return Err(BooksError::from_str(format!("Category {} has a {:?} normal balance but account {} has a {:?} normal balance.",
+                category.name, category.normal_balance, account.name, account.normal_balance()).as_str()))
+        }
+        Ok(())
+    }
+
+    /// Accounts grouped by category name, in the order of `accounts()`. Accounts without a category are
+    /// grouped under None.
+    pub fn accounts_by_category(&self) -> Vec<(Option<String>, Vec<Account>)> {
+        let mut groups: Vec<(Option<String>, Vec<Account>)> = Vec::new();
+        for a in self.accounts() {
+            let name = a.category.as_ref().map(|c| c.name.clone());
+            match groups.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, accounts)) => accounts.push(a),
+                None => groups.push((name, vec![a])),
+            }
+        }
+        groups
+    }
+
+    /// Set or clear an account's chart of accounts code. Codes must be unique.
+    pub fn set_account_code(&mut self, id: &Uuid, code: Option<String>) -> Result<(), BooksError> {
+        if !self.accounts.contains_key(id) {
+            return Err(BooksError::from_str(format!("Account {} not found.", id).as_str()));
+        }
+        if let Some(code) = &code {
+            self.check_code_unused(code, *id)?;
+        }
+        self.accounts.get_mut(id).unwrap().code = code;
+        Ok(())
+    }
+
+    fn check_code_unused(&self, code: &str, account_id: Uuid) -> Result<(), BooksError> {
+        match self.accounts.values().find(|a| a.id != account_id && a.code.as_deref() == Some(code)) {
+            Some(other) => Err(BooksError::from_str(format!("Account code {} is already used by {}.", code, other.name).as_str())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn account_by_code(&self, code: &str) -> Option<Account> {
+        self.accounts.values().find(|a| a.code.as_deref() == Some(code)).cloned()
+    }
+
+    /// ISO currency code of an account, the books' default currency if the account doesn't set one. None if
+    /// the account isn't found or neither sets a currency.
+    pub fn account_currency(&self, id: &Uuid) -> Option<String> {
+        let account = self.accounts.get(id)?;
+        account.currency.clone().or_else(|| Some(self.settings.default_currency.clone()).filter(|c| !c.is_empty()))
+    }
+
+    /// Balance of an account as of the date converted to the given currency. The rate function is given
+    /// the account's currency, the target currency and the date, and returns the rate to multiply by.
+    pub fn balance_in_currency<F>(&self, account_id: Uuid, date: NaiveDate, currency: &str, rate: F) -> Result<Decimal, BooksError>
+        where F: Fn(&str, &str, NaiveDate) -> Option<Decimal>
+    {
+        let balance = self.balance_as_of(account_id, date)?;
+        let from = match self.account_currency(&account_id) {
+            Some(c) => c,
+            None => return Err(BooksError::from_str(format!("Account {} has no currency.", account_id).as_str())),
+        };
+        if from == currency {
+            return Ok(balance)
+        }
+        match rate(from.as_str(), currency, date) {
+            Some(r) => Ok(self.settings.round(balance * r)),
+            None => Err(BooksError::from_str(format!("No exchange rate from {} to {} on {}", from, currency, date).as_str())),
+        }
+    }
+
+    /// Assets less liabilities as of the date, in the books' default currency. The rates function is given
+    /// an account's currency and the default currency and returns the rate to multiply by; it isn't called
+    /// for accounts already in the default currency. Fails if a rate is missing.
+    pub fn net_worth(&self, as_of: NaiveDate, rates: &dyn Fn(&str, &str) -> Option<Decimal>) -> Result<Decimal, BooksError> {
+        let base = &self.settings.default_currency;
+        let balances = self.balances_as_of(as_of);
+        let mut total = Decimal::ZERO;
+        for a in self.accounts.values() {
+            let sign = match a.account_type {
+                AccountType::Asset => Decimal::ONE,
+                AccountType::Liability => Decimal::NEGATIVE_ONE,
+                _ => continue,
+            };
+            let balance = balances[&a.id];
+            let currency = self.account_currency(&a.id).unwrap_or_default();
+            let converted = if currency == *base {
+                balance
+            } else {
+                match rates(currency.as_str(), base.as_str()) {
+                    Some(rate) => self.settings.round(balance * rate),
+                    None => return Err(BooksError::from_str(
+                        format!("No exchange rate from {} to {} for account {}", currency_name(&currency), currency_name(base), a.name).as_str())),
+                }
+            };
+            total += sign * converted;
+        }
+        Ok(total)
+    }
+
+    /// Create and add a new account in the books' default currency, returning a copy of it.
+    pub fn create_account(&mut self, name: &str, account_type: AccountType) -> Result<Account, BooksError> {
+        let mut account = Account::create_new(name, account_type);
+        if !self.settings.default_currency.is_empty() {
+            account.currency = Some(self.settings.default_currency.clone());
+        }
+        self.add_account(account.clone())?;
+        Ok(account)
     }
 
     pub fn delete_account(&mut self, id: &Uuid) -> Result<(), BooksError> {
@@ -56,10 +543,41 @@ impl Books {
             return Err(BooksError::from_str(format!("Account {} can not be deleted as it has transactions.", id).as_str()));
         }
 
+        if self.is_account_used(*id) {
+            return Err(BooksError::from_str(format!("Account {} can not be deleted as it is used by a schedule.", id).as_str()));
+        }
+
         self.accounts.remove(id);
         Ok(())
     }
 
+    /// Hide an account from `active_accounts`. Unlike deleting, this is allowed when the account has
+    /// transactions.
+    pub fn archive_account(&mut self, id: &Uuid) -> Result<(), BooksError> {
+        self.set_account_active(id, false)
+    }
+
+    pub fn unarchive_account(&mut self, id: &Uuid) -> Result<(), BooksError> {
+        self.set_account_active(id, true)
+    }
+
+    fn set_account_active(&mut self, id: &Uuid, active: bool) -> Result<(), BooksError> {
+        match self.accounts.get_mut(id) {
+            Some(account) => {
+                account.active = active;
+                Ok(())
+            },
+            None => Err(BooksError::from_str(format!("Account {} not found.", id).as_str())),
+        }
+    }
+
+    /// True if any transaction or schedule references the account.
+    pub fn is_account_used(&self, id: Uuid) -> bool {
+        self.transactions.iter().any(|t| t.involves_account(&id))
+            || self.scheduler.schedules().iter().any(|s| s.involves_account(&id))
+    }
+
+    /// Copies of the accounts, those with a code first in code order, then the rest by type and name.
     pub fn accounts(&self) -> Vec<Account> {
         let mut accounts_clone: Vec<Account> = Vec::new();
         for a in self.accounts.values() {
@@ -67,6 +585,10 @@ impl Books {
         }
 
         accounts_clone.sort_by(|a, b| {
+            let result = a.code.is_none().cmp(&b.code.is_none()).then_with(|| a.code.cmp(&b.code));
+            if result != Ordering::Equal {
+                return result
+            }
             let result = a.account_type.order().cmp(&b.account_type.order());
             if result == Ordering::Equal {
                 return a.name.cmp(&b.name)
@@ -76,32 +598,160 @@ impl Books {
         accounts_clone
     }
 
+    /// Accounts that haven't been archived, in the same order as `accounts()`.
+    pub fn active_accounts(&self) -> Vec<Account> {
+        self.accounts().into_iter().filter(|a| a.active).collect()
+    }
+
+    /// Add a transaction, keeping transactions in date order. The transaction is rejected, with the error
+    /// text given, when:
+    /// - it has no entries: "A transaction must have at least one entry"
+    /// - an entry's account doesn't exist: "Account not found for id: {account id}"
+    /// - an entry belongs to another transaction: "Entry {entry id} belongs to transaction {id}, not {id}"
+    /// - its accounts are in different currencies and no entry has a foreign amount to record the exchange:
+    ///   "Transaction mixes currencies {currency} and {currency} without an exchange entry"
+    /// - double entry is required and it has one entry: "A transaction needs at least two entries (double
+    ///   entry required is on)."
+    /// - double entry is required and it doesn't balance: "Transaction is out of balance by {difference}
+    ///   (debits {debits}, credits {credits})"
+    /// - descriptions are required and one is blank, or a description is too long.
     pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), BooksError> {
 
         if let Some(value) = self.validate_transaction(&transaction) {
             return value;
         }
 
-        self.transactions.push(transaction);
+        self.insert_sorted(transaction);
         Ok(())
     }
 
+    /// Add the transactions in a JSON array, each in the same shape as a serialized `Transaction`. Either all
+    /// are added or, if any is invalid or reuses the id of another transaction, none are. Returns the number
+    /// added.
+    pub fn import_transactions_json(&mut self, json: &str) -> Result<usize, BooksError> {
+        let transactions: Vec<Transaction> = serde_json::from_str(json)
+            .map_err(|e| BooksError{ error: format!("Failed to read transactions: {}", e) })?;
+        for (i, t) in transactions.iter().enumerate() {
+            if self.transactions.iter().chain(&transactions[..i]).any(|other| other.id == t.id) {
+                return Err(BooksError{ error: format!("Transaction {} ({}) is a duplicate.", i + 1, t.id) })
+            }
+            if let Some(Err(e)) = self.validate_transaction(t) {
+                return Err(BooksError{ error: format!("Transaction {} ({}) is invalid: {}", i + 1, t.id, e.error) })
+            }
+        }
+        let count = transactions.len();
+        transactions.into_iter().for_each(|t| self.insert_sorted(t));
+        Ok(count)
+    }
+
+    /// Check a transaction as `add_transaction` would, without adding it.
+    pub(crate) fn check_transaction(&mut self, transaction: &Transaction) -> Result<(), BooksError> {
+        self.validate_transaction(transaction).unwrap_or(Ok(()))
+    }
+
+    /// Insert a transaction after any others on the same date, keeping transactions in date order.
+    fn insert_sorted(&mut self, transaction: Transaction) {
+        let date = transaction.date();
+        let index = self.transactions.partition_point(|t| t.date() <= date);
+        self.transactions.insert(index, transaction);
+        self.index_inserted(index, date);
+    }
+
+    /// Add a transaction inserted at the position to the date index, if it has been built, moving along the
+    /// positions after it. As transactions are in date order only those on the same or later dates move.
+    fn index_inserted(&mut self, position: usize, date: Option<NaiveDate>) {
+        if let Some(index) = self.date_index.get_mut() {
+            let later = match date {
+                Some(d) => index.range_mut(d..),
+                None => index.range_mut::<NaiveDate, _>(..),
+            };
+            later.flat_map(|(_, positions)| positions.iter_mut()).filter(|i| **i >= position).for_each(|i| *i += 1);
+            if let Some(date) = date {
+                index.entry(date).or_default().push(position);
+            }
+        }
+    }
+
+    /// Remove a transaction removed from the position from the date index, if it has been built, moving
+    /// back the positions after it.
+    fn index_removed(&mut self, position: usize, date: Option<NaiveDate>) {
+        if let Some(index) = self.date_index.get_mut() {
+            if let Some(positions) = date.and_then(|d| index.get_mut(&d)) {
+                positions.retain(|i| *i != position);
+                if positions.is_empty() {
+                    index.remove(&date.unwrap());
+                }
+            }
+            let later = match date {
+                Some(d) => index.range_mut(d..),
+                None => index.range_mut::<NaiveDate, _>(..),
+            };
+            later.flat_map(|(_, positions)| positions.iter_mut()).filter(|i| **i > position).for_each(|i| *i -= 1);
+        }
+    }
+
+    fn date_index(&self) -> &BTreeMap<NaiveDate, Vec<usize>> {
+        self.date_index.get_or_init(|| {
+            let mut index: BTreeMap<NaiveDate, Vec<usize>> = BTreeMap::new();
+            for (i, t) in self.transactions.iter().enumerate() {
+                if let Some(date) = t.date() {
+                    index.entry(date).or_default().push(i);
+                }
+            }
+            index
+        })
+    }
+
     fn validate_transaction(&mut self, transaction: &Transaction) -> Option<Result<(), BooksError>> {
 
+        if transaction.entries.is_empty() {
+            return Some(Err(BooksError::from_str("A transaction must have at least one entry")))
+        }
+
         for e in transaction.entries.as_slice() {
             if !self.valid_account_id(Some(e.account_id)) {
                 return Some(Err(BooksError{ error: format!("Account not found for id: {}", e.account_id) }))
             }
         }
 
+        if let Some(e) = transaction.entries.iter().find(|e| e.transaction_id != transaction.id) {
+            return Some(Err(BooksError::from_str(
+                format!("Entry {} belongs to transaction {}, not {}", e.id, e.transaction_id, transaction.id).as_str())))
+        }
+
+        let has_exchange_entry = transaction.entries.iter().any(|e| e.currency.is_some() && e.foreign_amount.is_some());
+        if !has_exchange_entry {
+            let first = self.account_currency(&transaction.entries[0].account_id).unwrap_or_default();
+            if let Some(other) = transaction.entries.iter()
+                .map(|e| self.account_currency(&e.account_id).unwrap_or_default())
+                .find(|c| *c != first) {
+                return Some(Err(BooksError::from_str(
+                    format!("Transaction mixes currencies {} and {} without an exchange entry", currency_name(&first), currency_name(&other)).as_str())))
+            }
+        }
+
         if self.settings.require_double_entry && transaction.entries.len() < 2 {
             return Some(Err(BooksError::from_str("A transaction needs at least two entries (double entry required is on).")))
-        } else if transaction.entries.len() < 1 {
-            return Some(Err(BooksError::from_str("A transaction must have at least one entry")))
         }
 
-        if !self.valid_account_id(Some(transaction.entries[0].account_id)) {
-            return Some(Err(BooksError::from_str("Invalid Account")))
+        if self.settings.require_double_entry && !self.settings.allow_unbalanced && transaction.kind != TransactionKind::Adjustment {
+            let total = |side| transaction.entries.iter().filter(|e| e.entry_type == side).fold(Decimal::ZERO, |total, e| total + e.amount);
+            let (debits, credits) = (total(Side::Debit), total(Side::Credit));
+            if debits != credits {
+                return Some(Err(BooksError::from_str(
+                    format!("Transaction is out of balance by {:.2} (debits {:.2}, credits {:.2})", (debits - credits).abs(), debits, credits).as_str())))
+            }
+        }
+
+        if self.settings.require_descriptions && transaction.entries.iter().any(|e| e.description.trim().is_empty()) {
+            return Some(Err(BooksError::from_str("Every entry needs a description (descriptions required is on).")))
+        }
+
+        if let Some(max) = self.settings.max_description_length {
+            if let Some(e) = transaction.entries.iter().find(|e| e.description.chars().count() > max) {
+                return Some(Err(BooksError::from_str(
+                    format!("Description is {} characters long, the maximum is {}: {}", e.description.chars().count(), max, e.description).as_str())))
+            }
         }
         None
     }
@@ -113,7 +763,13 @@ impl Books {
         }
 
         if let Some(index) = self.transactions.iter().position(|t| t.id == transaction.id) {
-            let _old = std::mem::replace(&mut self.transactions[index], transaction);
+            if self.transactions[index].date() == transaction.date() {
+                let _old = std::mem::replace(&mut self.transactions[index], transaction);
+            } else {
+                let old = self.transactions.remove(index);
+                self.index_removed(index, old.date());
+                self.insert_sorted(transaction);
+            }
             Ok(())
         } else {
             Err(BooksError { error: "Transaction not found".to_string() })
@@ -121,10 +777,34 @@ impl Books {
 
     }
 
+    /// Add a copy of a transaction on a new date, with new ids, e.g. to re-enter a similar transaction. The
+    /// copy is recorded rather than scheduled and isn't related to other transactions. Returns its id.
+    pub fn duplicate_transaction(&mut self, id: Uuid, new_date: NaiveDate) -> Result<Uuid, BooksError> {
+        let mut transaction = match self.transactions.iter().find(|t| t.id == id) {
+            Some(t) => t.clone(),
+            None => return Err(BooksError::from_str(format!("Transaction {} not found.", id).as_str())),
+        };
+        transaction.id = Uuid::new_v4();
+        transaction.status = TransactionStatus::Recorded;
+        transaction.source = TransactionSource::Manual;
+        transaction.related = Vec::new();
+        for e in transaction.entries.iter_mut() {
+            e.id = Uuid::new_v4();
+            e.transaction_id = transaction.id;
+            e.date = new_date;
+            e.balance = None;
+            e.reconciled = false;
+        }
+        let transaction_id = transaction.id;
+        self.add_transaction(transaction)?;
+        Ok(transaction_id)
+    }
+
     pub fn delete_transaction(&mut self, id: &Uuid) -> Result<(), BooksError> {
         if let Some(index) = self.transactions.iter().position(|t| t.id == *id) {
             println!("remove: {:?}", index);
-            self.transactions.remove(index);
+            let removed = self.transactions.remove(index);
+            self.index_removed(index, removed.date());
             Ok(())
         } else {
             return Err(BooksError::from_str(format!("Transaction {} not found.", id).as_str()));
@@ -132,500 +812,3427 @@ impl Books {
     }
 
 
-    pub fn transactions(&self) -> &[Transaction] {
-        self.transactions.as_slice()
+    /// Mark a transaction as approved, so it counts towards balances when the books require approval.
+    pub fn approve_transaction(&mut self, id: Uuid) -> Result<(), BooksError> {
+        match self.transactions.iter_mut().find(|t| t.id == id) {
+            Some(t) => {
+                t.approved = true;
+                Ok(())
+            },
+            None => Err(BooksError::from_str(format!("Transaction {} not found.", id).as_str())),
+        }
     }
 
-    pub fn transaction(&self, transaction_id: Uuid) ->  Option<Transaction> {
-        let matches:Vec<Transaction> = self.transactions.iter()
-            .filter(|t|t.id == transaction_id)
-            .map(|t| t.clone())
-            .collect();
-
-        if matches.len() > 0 {
-            return Some(matches[0].clone())
-        }
+    /// Mark an entry as reconciled against a statement.
+    pub fn reconcile_entry(&mut self, transaction_id: Uuid, entry_id: Uuid) -> Result<(), BooksError> {
+        self.set_reconciled(transaction_id, entry_id, true)
+    }
 
-        None
+    pub fn unreconcile_entry(&mut self, transaction_id: Uuid, entry_id: Uuid) -> Result<(), BooksError> {
+        self.set_reconciled(transaction_id, entry_id, false)
     }
 
-    /// Get a copy of the transactions with balances for a given Account.
-    pub fn account_entries(&self, account_id: Uuid) -> Result<Vec<Entry>, BooksError> {
-        if !self.accounts.contains_key(&account_id) {
-            return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str()));
+    fn set_reconciled(&mut self, transaction_id: Uuid, entry_id: Uuid, reconciled: bool) -> Result<(), BooksError> {
+        let transaction = match self.transactions.iter_mut().find(|t| t.id == transaction_id) {
+            Some(t) => t,
+            None => return Err(BooksError::from_str(format!("Transaction {} not found.", transaction_id).as_str())),
+        };
+        match transaction.entries.iter_mut().find(|e| e.id == entry_id) {
+            Some(e) => {
+                e.reconciled = reconciled;
+                Ok(())
+            },
+            None => Err(BooksError::from_str(format!("Entry {} not found in transaction {}.", entry_id, transaction_id).as_str())),
         }
+    }
 
-        let mut account_transactions: Vec<Transaction> =
-            self.transactions
+    /// Mark entries cleared (reconciled) or not from CSV with a header row and columns id and cleared
+    /// (true/false, yes/no or 1/0). The id is a transaction id or reference (an entry's
+    /// `REFERENCE_DIMENSION`), for all the transaction's entries, or an entry id. Nothing is applied if any
+    /// row is invalid or has an unknown id; the error lists every unknown id. Returns the number of
+    /// entries updated.
+    pub fn apply_cleared_csv(&mut self, csv: &str) -> Result<usize, BooksError> {
+        let mut updates: Vec<(Uuid, Uuid, bool)> = Vec::new();
+        let mut unknown: Vec<String> = Vec::new();
+        for (i, line) in csv.lines().enumerate().skip(1).filter(|(_, l)| !l.trim().is_empty()) {
+            let invalid = |reason: &str| BooksError::from_str(format!("Invalid row {}: {}", i + 1, reason).as_str());
+            let fields = csv_fields(line);
+            if fields.len() < 2 {
+                return Err(invalid("expected id and cleared"))
+            }
+            let cleared = match fields[1].trim().to_lowercase().as_str() {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                other => return Err(invalid(&format!("bad cleared flag {}", other))),
+            };
+            let id = fields[0].trim();
+            let uuid = Uuid::parse_str(id).ok();
+            let matched: Vec<(Uuid, Uuid)> = self.transactions
                 .iter()
-                .filter(|t|t.involves_account(&account_id))
-                .map(|t| t.clone())
+                .flat_map(|t| {
+                    let whole = uuid == Some(t.id)
+                        || t.entries.iter().any(|e| e.dimensions.get(REFERENCE_DIMENSION).is_some_and(|r| r == id));
+                    t.entries.iter().filter(move |e| whole || uuid == Some(e.id)).map(|e| (e.transaction_id, e.id))
+                })
                 .collect();
+            if matched.is_empty() {
+                unknown.push(format!("{} (row {})", id, i + 1));
+            }
+            updates.extend(matched.into_iter().map(|(transaction_id, entry_id)| (transaction_id, entry_id, cleared)));
+        }
+        if !unknown.is_empty() {
+            return Err(BooksError::from_str(format!("Unknown ids: {}", unknown.join(", ")).as_str()))
+        }
 
-        account_transactions.sort_by(|a, b| a.entries[0].date.cmp(&b.entries[0].date));
-        let account = self.accounts.get(&account_id).unwrap();
-        let mut balance = account.starting_balance;
-        let mut account_entries: Vec<Entry> = Vec::new();
-        account_transactions
+        for (transaction_id, entry_id, cleared) in &updates {
+            self.set_reconciled(*transaction_id, *entry_id, *cleared)?;
+        }
+        Ok(updates.len())
+    }
+
+    /// Recorded entries for an Account not yet reconciled, in date order.
+    pub fn unreconciled_entries(&self, account_id: Uuid) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = self.transactions
             .iter()
-            .for_each(|t| t.account_entries(account_id)
-                .iter()
-                .for_each(|e|{
-                    if e.entry_type == account.normal_balance() {
-                        balance = balance + e.amount;
-                    } else {
-                        balance = balance - e.amount;
-                    };
-                    let mut new_e = e.clone();
-                    new_e.set_balance(Some(balance.clone()));
-                    account_entries.push(new_e);
-                }
-                )
-            );
+            .filter(|t| t.status == TransactionStatus::Recorded)
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.account_id == account_id && !e.reconciled)
+            .cloned()
+            .collect();
+        entries.sort_by_key(|e| e.date);
+        entries
+    }
 
-        account_entries.sort_by(|a, b| a.date.cmp(&b.date));
-        Ok(account_entries)
+    /// True if the transaction counts towards balances, i.e. it is approved or approval isn't required.
+    fn is_posted(&self, transaction: &Transaction) -> bool {
+        transaction.approved || !self.settings.require_approval
     }
 
-    /// Get a copy of the transactions with balances for a given Account.
-    pub fn account_transactions(&self, account_id: Uuid) -> Result<Vec<Transaction>, BooksError> {
-        if !self.accounts.contains_key(&account_id) {
-            return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str()));
-        }
+    pub fn transactions(&self) -> &[Transaction] {
+        self.transactions.as_slice()
+    }
 
-        let mut account_transactions: Vec<Transaction> =
-            self.transactions
-                .iter()
-                .filter(|t|t.involves_account(&account_id))
-                .map(|t| t.clone())
-                .collect();
+    /// Get a copy of the recorded transactions dated after today, which are usually data entry mistakes.
+    pub fn future_dated_transactions(&self, today: NaiveDate) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.status == TransactionStatus::Recorded && t.date().is_some_and(|d| d > today))
+            .cloned()
+            .collect()
+    }
 
-        account_transactions.sort_by(
-            |a, b| a.find_entry_by_account(&account_id).unwrap().date.cmp(&b.find_entry_by_account(&account_id).unwrap().date));
-        let account = self.accounts.get(&account_id).unwrap();
-        let mut balance = account.starting_balance;
+    /// Entries dated before their account's opened date, as (entry id, entry date).
+    pub fn validate_account_dates(&self) -> Vec<(Uuid, NaiveDate)> {
+        self.transactions
+            .iter()
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| self.accounts.get(&e.account_id).and_then(|a| a.opened).is_some_and(|opened| e.date < opened))
+            .map(|e| (e.id, e.date))
+            .collect()
+    }
 
-        for i in 0..account_transactions.len() {
-            balance = account_transactions[i].update_balance(balance, account);
+    /// Get a copy of the transactions dated within the given range (inclusive), in date order. A transaction's
+    /// date is that of its first entry, see `Transaction::date`, the same date transactions are sorted by.
+    /// Entries dated differently from the first don't affect whether the transaction is included.
+    pub fn transactions_between(&self, start: NaiveDate, end: NaiveDate) -> Vec<Transaction> {
+        if start > end {
+            return Vec::new()
         }
-        Ok(account_transactions)
+        self.date_index()
+            .range(start..=end)
+            .flat_map(|(_, positions)| positions.iter().map(|i| self.transactions[*i].clone()))
+            .collect()
     }
 
-
-    pub fn add_schedule(&mut self, schedule: Schedule) -> Result<(), BooksError> {
-        if let Some(value) = self.validate_schedule(&schedule) {
-            return value;
+    /// Record two transactions as related to each other, e.g. a payable and its settlement.
+    pub fn link_transactions(&mut self, a: Uuid, b: Uuid) -> Result<(), BooksError> {
+        for id in [a, b] {
+            if !self.transactions.iter().any(|t| t.id == id) {
+                return Err(BooksError::from_str(format!("Transaction {} not found.", id).as_str()));
+            }
+        }
+        for (id, other) in [(a, b), (b, a)] {
+            let transaction = self.transactions.iter_mut().find(|t| t.id == id).unwrap();
+            if !transaction.related.contains(&other) {
+                transaction.related.push(other);
+            }
         }
-
-        self.scheduler.add_schedule(schedule);
         Ok(())
     }
 
-    fn validate_schedule(&mut self, schedule: &Schedule) -> Option<Result<(), BooksError>> {
-
-        if schedule.entries.len() < 1 {
+    /// Get a copy of the transactions related to the given transaction.
+    pub fn related_transactions(&self, id: Uuid) -> Vec<Transaction> {
+        let related = match self.transactions.iter().find(|t| t.id == id) {
+            Some(t) => &t.related,
+            None => return Vec::new(),
+        };
+        self.transactions
+            .iter()
+            .filter(|t| related.contains(&t.id))
+            .cloned()
+            .collect()
+    }
+
+    /// Distinct entry descriptions starting with the prefix (ignoring case), most used first, e.g. for
+    /// autocomplete. At most `limit` descriptions are returned.
+    pub fn description_suggestions(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        self.transactions
+            .iter()
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| !e.description.trim().is_empty() && e.description.to_lowercase().starts_with(&prefix))
+            .for_each(|e| *counts.entry(e.description.as_str()).or_default() += 1);
+
+        let mut suggestions: Vec<(&str, usize)> = counts.into_iter().collect();
+        suggestions.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        suggestions.into_iter().take(limit).map(|(d, _)| d.to_string()).collect()
+    }
+
+    /// Each entry of a transaction as (account name, side, amount), for display. Accounts that can't be found
+    /// are shown by id.
+    pub fn describe_transaction(&self, id: Uuid) -> Option<Vec<(String, Side, Decimal)>> {
+        let transaction = self.transactions.iter().find(|t| t.id == id)?;
+        Some(transaction.entries
+            .iter()
+            .map(|e| {
+                let name = self.accounts.get(&e.account_id).map(|a| a.name.clone()).unwrap_or(e.account_id.to_string());
+                (name, e.entry_type, e.amount)
+            })
+            .collect())
+    }
+
+    /// Get a copy of the transactions with any entry on the given date, e.g. for a daybook.
+    pub fn daybook(&self, date: NaiveDate) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.entries.iter().any(|e| e.date == date))
+            .cloned()
+            .collect()
+    }
+
+    /// Get a copy of the transactions with any entry whose description contains the query, ignoring case.
+    /// If the query is a number, entries for that amount (ignoring sign) match too.
+    pub fn search_transactions(&self, query: &str) -> Vec<Transaction> {
+        let needle = query.trim().to_lowercase();
+        let amount = Decimal::from_str_exact(query.trim()).ok().map(|a| a.abs());
+        self.transactions
+            .iter()
+            .filter(|t| t.entries.iter().any(|e| {
+                e.description.to_lowercase().contains(&needle) || amount.is_some_and(|a| e.amount.abs() == a)
+            }))
+            .cloned()
+            .collect()
+    }
+
+    /// Get a copy of the transactions with any entry for exactly the given amount, ignoring sign.
+    pub fn transactions_with_amount(&self, amount: Decimal) -> Vec<Transaction> {
+        self.transactions_with_amount_approx(amount, Decimal::ZERO)
+    }
+
+    /// Get a copy of the transactions with any entry within epsilon of the given amount, ignoring sign.
+    pub fn transactions_with_amount_approx(&self, amount: Decimal, epsilon: Decimal) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.entries.iter().any(|e| (e.amount.abs() - amount.abs()).abs() <= epsilon))
+            .cloned()
+            .collect()
+    }
+
+    /// Settle a foreign currency payable (or receivable) booked by an earlier transaction, i.e. its foreign
+    /// currency entry on a liability (or asset) account. The payment is converted at the settlement rate and
+    /// rounded to the decimal places of the payable account's currency, while the payable is cleared at its
+    /// booked amount, with the difference posted to the FX gain/loss account. The payment account, e.g. the
+    /// bank account paid from, is needed for the settlement to balance as the payable transaction doesn't
+    /// say how it will be paid. Returns the id of the settlement transaction.
+    pub fn settle_foreign(&mut self, payable_id: Uuid, payment_account: Uuid, settlement_rate: Decimal, fx_account: Uuid, date: NaiveDate) -> Result<Uuid, BooksError> {
+        let payable = match self.transactions.iter().find(|t| t.id == payable_id) {
+            Some(t) => t,
+            None => return Err(BooksError::from_str(format!("Transaction {} not found.", payable_id).as_str())),
+        };
+        let is_balance_sheet = |e: &Entry| self.accounts.get(&e.account_id)
+            .is_some_and(|a| matches!(a.account_type, AccountType::Asset | AccountType::Liability));
+        let foreign_entries: Vec<&Entry> = payable.entries.iter().filter(|e| e.currency.is_some() && e.foreign_amount.is_some()).collect();
+        let booked = match foreign_entries.iter().find(|e| is_balance_sheet(e)).or(foreign_entries.first()) {
+            Some(e) => (*e).clone(),
+            None => return Err(BooksError::from_str(format!("Transaction {} has no foreign currency entry.", payable_id).as_str())),
+        };
+
+        let foreign_amount = booked.foreign_amount.unwrap();
+        let currency = self.account_currency(&booked.account_id);
+        let settled = self.settings.round_for_currency(foreign_amount * settlement_rate, currency.as_deref());
+        let difference = settled - booked.amount;
+        let transaction_id = Uuid::new_v4();
+        let entry = |account_id, entry_type, amount| Entry {
+            id: Uuid::new_v4(),
+            transaction_id,
+            date,
+            description: format!("Settlement of {}", booked.description),
+            account_id,
+            entry_type,
+            amount,
+            balance: None,
+            currency: booked.currency.clone(),
+            foreign_amount: Some(foreign_amount),
+            reconciled: false,
+            exclude_from_reports: false,
+            dimensions: HashMap::new(),
+        };
+
+        let mut entries = vec![
+            entry(booked.account_id, booked.entry_type.opposite(), booked.amount),
+            entry(payment_account, booked.entry_type, settled),
+        ];
+        if difference > Decimal::ZERO {
+            entries.push(entry(fx_account, booked.entry_type.opposite(), difference));
+        } else if difference < Decimal::ZERO {
+            entries.push(entry(fx_account, booked.entry_type, -difference));
+        }
+        entries[2..].iter_mut().for_each(|e| { e.currency = None; e.foreign_amount = None; });
+
+        self.add_transaction(Transaction {
+            id: transaction_id,
+            entries,
+            status: TransactionStatus::Recorded,
+            source: TransactionSource::Manual,
+            kind: TransactionKind::Normal,
+            approved: true,
+            related: Vec::new(),
+        })?;
+        self.link_transactions(payable_id, transaction_id)?;
+        Ok(transaction_id)
+    }
+
+    /// Close each period (calendar month, quarter or year) ending on or before the given date, posting a closing
+    /// transaction at the end of each period which moves that period's revenue and expenses into the
+    /// retained earnings account. Only posted, recorded transactions are closed. Periods with nothing left
+    /// to close are skipped, so closing again is safe. Returns the ids of the closing transactions.
+    pub fn close_periods(&mut self, through: NaiveDate, retained_earnings: Uuid, period: ScheduleEnum) -> Result<Vec<Uuid>, BooksError> {
+        if !self.accounts.contains_key(&retained_earnings) {
+            return Err(BooksError::from_str(format!("Account not found for id {}", retained_earnings).as_str()));
+        }
+        if !matches!(period, ScheduleEnum::Months | ScheduleEnum::Quarters | ScheduleEnum::Years) {
+            return Err(BooksError::from_str("Periods can only be closed by month, quarter or year"));
+        }
+
+        let first_date = self.transactions.iter()
+            .filter(|t| self.is_posted(t) && t.status != TransactionStatus::Projected)
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| self.accounts.get(&e.account_id).is_some_and(|a| a.account_type.is_income_statement()))
+            .map(|e| e.date)
+            .min();
+        let mut start = match first_date {
+            Some(d) if period == ScheduleEnum::Months => NaiveDate::from_ymd_opt(d.year(), d.month(), 1).unwrap(),
+            Some(d) if period == ScheduleEnum::Quarters => NaiveDate::from_ymd_opt(d.year(), d.month0() / 3 * 3 + 1, 1).unwrap(),
+            Some(d) => NaiveDate::from_ymd_opt(d.year(), 1, 1).unwrap(),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut closing_ids = Vec::new();
+        loop {
+            let next_start = period.shift(start, 1);
+            let end = next_start.pred_opt().unwrap();
+            if end > through {
+                break;
+            }
+            if let Some(id) = self.close_period(start, end, retained_earnings)? {
+                closing_ids.push(id);
+            }
+            start = next_start;
+        }
+        Ok(closing_ids)
+    }
+
+    fn close_period(&mut self, start: NaiveDate, end: NaiveDate, retained_earnings: Uuid) -> Result<Option<Uuid>, BooksError> {
+        let mut net_changes: HashMap<Uuid, Decimal> = HashMap::new();
+        let posted = self.transactions.iter().filter(|t| self.is_posted(t) && t.status != TransactionStatus::Projected);
+        for e in posted.flat_map(|t| t.entries.iter()) {
+            if e.date < start || e.date > end {
+                continue;
+            }
+            if let Some(account) = self.accounts.get(&e.account_id).filter(|a| a.account_type.is_income_statement()) {
+                *net_changes.entry(account.id).or_insert(Decimal::ZERO) += e.signed_amount(Side::Debit);
+            }
+        }
+
+        let transaction_id = Uuid::new_v4();
+        let entry = |account_id, debit_amount: Decimal| Entry {
+            id: Uuid::new_v4(),
+            transaction_id,
+            date: end,
+            description: format!("Closing for {} to {}", start, end),
+            account_id,
+            entry_type: if debit_amount > Decimal::ZERO { Side::Debit } else { Side::Credit },
+            amount: debit_amount.abs(),
+            balance: None,
+            currency: None,
+            foreign_amount: None,
+            reconciled: false,
+            exclude_from_reports: false,
+            dimensions: HashMap::new(),
+        };
+
+        let mut entries: Vec<Entry> = self.accounts()
+            .iter()
+            .filter_map(|a| net_changes.get(&a.id).filter(|n| !n.is_zero()).map(|n| entry(a.id, -*n)))
+            .collect();
+        if entries.is_empty() {
+            return Ok(None)
+        }
+        let net_income = net_changes.values().fold(Decimal::ZERO, |total, n| total + n);
+        if !net_income.is_zero() {
+            entries.push(entry(retained_earnings, net_income));
+        }
+
+        self.add_transaction(Transaction {
+            id: transaction_id,
+            entries,
+            status: TransactionStatus::Recorded,
+            source: TransactionSource::Manual,
+            kind: TransactionKind::Closing,
+            approved: true,
+            related: Vec::new(),
+        })?;
+        Ok(Some(transaction_id))
+    }
+
+    /// Get a copy of the transactions whose debits and credits don't balance. Adjustments are
+    /// deliberately one sided so are not included.
+    pub fn find_unbalanced(&self) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.kind != TransactionKind::Adjustment && !t.is_balanced())
+            .cloned()
+            .collect()
+    }
+
+    /// Likely reasons the trial balance doesn't balance, one line each with the amount involved: unbalanced
+    /// transactions, single sided transactions and starting balances not offset to equity.
+    pub fn trial_balance_diagnostics(&self) -> Vec<String> {
+        let mut diagnostics: Vec<String> = Vec::new();
+        let date = |t: &Transaction| t.date().map(|d| d.to_string()).unwrap_or("no date".to_string());
+        let description = |t: &Transaction| t.entries.first().map(|e| e.description.clone()).unwrap_or_default();
+
+        for t in self.find_unbalanced().iter().filter(|t| t.entries.len() != 1) {
+            let difference = t.entries.iter().fold(Decimal::ZERO, |total, e| total + e.signed_amount(Side::Debit));
+            diagnostics.push(format!("Transaction {} on {} \"{}\" is unbalanced, debits exceed credits by {}", t.id, date(t), description(t), difference));
+        }
+        for t in self.single_sided_transactions() {
+            let e = &t.entries[0];
+            diagnostics.push(format!("Transaction {} on {} \"{}\" has a single {:?} entry of {}", t.id, date(&t), e.description, e.entry_type, e.amount));
+        }
+
+        let starting_total = self.accounts.values()
+            .fold(Decimal::ZERO, |total, a| match a.normal_balance() {
+                Side::Debit => total + a.starting_balance,
+                Side::Credit => total - a.starting_balance,
+            });
+        if !starting_total.is_zero() {
+            diagnostics.push(format!("Starting balances are not offset to equity, debits exceed credits by {}", starting_total));
+            for a in self.accounts().iter().filter(|a| !a.starting_balance.is_zero()) {
+                diagnostics.push(format!("Account {} has a starting balance of {}", a.name, a.starting_balance));
+            }
+        }
+        diagnostics
+    }
+
+    /// Get a copy of the transactions with only one entry, i.e. still missing a counter entry.
+    pub fn single_sided_transactions(&self) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.entries.len() == 1)
+            .cloned()
+            .collect()
+    }
+
+    pub fn transaction(&self, transaction_id: Uuid) ->  Option<Transaction> {
+        let matches:Vec<Transaction> = self.transactions.iter()
+            .filter(|t|t.id == transaction_id)
+            .map(|t| t.clone())
+            .collect();
+
+        if matches.len() > 0 {
+            return Some(matches[0].clone())
+        }
+
+        None
+    }
+
+    /// Get a copy of the transactions with balances for a given Account. Entries are in date order, and
+    /// entries on the same date are in the order their transactions were added, so the running balances
+    /// are the same every time. Entries before the starting balance date have no balance.
+    pub fn account_entries(&self, account_id: Uuid) -> Result<Vec<Entry>, BooksError> {
+        if !self.accounts.contains_key(&account_id) {
+            return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str()));
+        }
+
+        let mut account_transactions: Vec<(usize, &Transaction)> =
+            self.transactions
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.involves_account(&account_id))
+                .collect();
+
+        account_transactions.sort_by_key(|(position, t)| (t.find_entry_by_account(&account_id).map(|e| e.date), *position));
+        let account = self.accounts.get(&account_id).unwrap();
+        let mut balance = account.starting_balance;
+        let mut account_entries: Vec<Entry> = Vec::new();
+        account_transactions
+            .iter()
+            .for_each(|(_, t)| t.account_entries(account_id)
+                .iter()
+                .for_each(|e|{
+                    if !self.is_posted(t) || !account.after_starting_balance(e.date) {
+                        let mut new_e = e.clone();
+                        new_e.set_balance(None);
+                        account_entries.push(new_e);
+                        return;
+                    }
+                    if e.entry_type == account.normal_balance() {
+                        balance = balance + e.amount;
+                    } else {
+                        balance = balance - e.amount;
+                    };
+                    let mut new_e = e.clone();
+                    new_e.set_balance(Some(balance.clone()));
+                    account_entries.push(new_e);
+                }
+                )
+            );
+
+        account_entries.sort_by(|a, b| a.date.cmp(&b.date));
+        Ok(account_entries)
+    }
+
+    /// Write an account's entries as CSV with columns date, description, debit, credit and the running
+    /// balance, as given by `account_entries`. Amounts are in the account currency's accounting style, so a
+    /// negative balance is e.g. "(100.00)". Entries without a balance, e.g. unapproved ones, leave it blank.
+    pub fn export_account_csv<W: Write>(&self, account_id: Uuid, mut writer: W) -> Result<(), BooksError> {
+        let entries = self.account_entries(account_id)?;
+        let currency = self.account_currency(&account_id).unwrap_or_default();
+        let amount = |a: Decimal| format_accounting(a, &currency);
+        let write_error = |e: std::io::Error| BooksError { error: format!("Failed to write CSV: {}", e) };
+        writer.write_all(b"date,description,debit,credit,balance\n").map_err(write_error)?;
+        for e in entries {
+            let (debit, credit) = match e.entry_type {
+                Side::Debit => (amount(e.amount), String::new()),
+                Side::Credit => (String::new(), amount(e.amount)),
+            };
+            let balance = e.balance.map(amount).unwrap_or_default();
+            writeln!(writer, "{},{},{},{},{}", e.date, csv_field(&e.description), debit, credit, balance).map_err(write_error)?;
+        }
+        Ok(())
+    }
+
+    /// Set the running balance on every entry, per account in date order, e.g. after an import.
+    pub fn recompute_entry_balances(&mut self) {
+        let mut positions: Vec<(NaiveDate, usize, usize)> = Vec::new();
+        for (t, transaction) in self.transactions.iter().enumerate() {
+            for (e, entry) in transaction.entries.iter().enumerate() {
+                positions.push((entry.date, t, e));
+            }
+        }
+        positions.sort_by_key(|(date, _, _)| *date);
+
+        let mut balances: HashMap<Uuid, Decimal> = HashMap::new();
+        for (_, t, e) in positions {
+            let posted = self.is_posted(&self.transactions[t]);
+            let entry = &mut self.transactions[t].entries[e];
+            match self.accounts.get(&entry.account_id) {
+                Some(account) if !posted || !account.after_starting_balance(entry.date) => entry.set_balance(None),
+                Some(account) => {
+                    let balance = balances.entry(account.id).or_insert(account.starting_balance);
+                    *balance += entry.signed_amount(account.normal_balance());
+                    entry.set_balance(Some(*balance));
+                },
+                None => entry.set_balance(None),
+            }
+        }
+    }
+
+    /// Get a copy of the transactions with balances for a given Account.
+    pub fn account_transactions(&self, account_id: Uuid) -> Result<Vec<Transaction>, BooksError> {
+        if !self.accounts.contains_key(&account_id) {
+            return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str()));
+        }
+
+        let mut account_transactions: Vec<Transaction> =
+            self.transactions
+                .iter()
+                .filter(|t|t.involves_account(&account_id))
+                .map(|t| t.clone())
+                .collect();
+
+        // Transactions without an entry for the account should have been filtered out above, if any slip
+        // through they sort first (stable) rather than panicking.
+        account_transactions.sort_by_key(|t| t.find_entry_by_account(&account_id).map(|e| e.date));
+        let account = self.accounts.get(&account_id).unwrap();
+        let mut balance = account.starting_balance;
+
+        for i in 0..account_transactions.len() {
+            if self.is_posted(&account_transactions[i]) {
+                balance = account_transactions[i].update_balance(balance, account);
+            }
+        }
+        Ok(account_transactions)
+    }
+
+    /// Balance of an Account including all entries dated on or before the given date, and on or after its
+    /// starting balance date if it has one.
+    pub fn balance_as_of(&self, account_id: Uuid, date: NaiveDate) -> Result<Decimal, BooksError> {
+        let account = match self.accounts.get(&account_id) {
+            Some(a) => a,
+            None => return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str())),
+        };
+
+        let normal_balance = account.normal_balance();
+        let balance = self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t))
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.account_id == account_id && e.date <= date && account.after_starting_balance(e.date))
+            .fold(account.starting_balance, |balance, e| balance + e.signed_amount(normal_balance));
+        Ok(balance)
+    }
+
+    /// Combined balance of a group of Accounts as of the given date. The total is expressed relative to
+    /// the normal balance of the first account, so accounts with the opposite normal balance (e.g. a credit
+    /// card in a group of bank accounts) reduce the total.
+    pub fn group_balance(&self, account_ids: &[Uuid], as_of: NaiveDate) -> Result<Decimal, BooksError> {
+        let mut total = Decimal::ZERO;
+        let mut group_side = None;
+        for id in account_ids {
+            let balance = self.balance_as_of(*id, as_of)?;
+            let side = self.accounts[id].normal_balance();
+            if *group_side.get_or_insert(side) == side {
+                total += balance;
+            } else {
+                total -= balance;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Equity implied by the accounting identity, i.e. assets less liabilities, as of the given date.
+    pub fn computed_equity(&self, as_of: NaiveDate) -> Decimal {
+        let balances = self.balances_as_of(as_of);
+        self.accounts.values().fold(Decimal::ZERO, |total, a| match a.account_type {
+            AccountType::Asset => total + balances[&a.id],
+            AccountType::Liability => total - balances[&a.id],
+            _ => total,
+        })
+    }
+
+    /// Check the computed equity against the equity accounts, including any revenue less expenses not
+    /// yet closed to equity. A mismatch means the books don't balance.
+    pub fn equity_matches_accounts(&self, as_of: NaiveDate) -> bool {
+        let balances = self.balances_as_of(as_of);
+        let equity = self.accounts.values().fold(Decimal::ZERO, |total, a| match a.account_type {
+            AccountType::Equity | AccountType::Revenue => total + balances[&a.id],
+            AccountType::Expense => total - balances[&a.id],
+            _ => total,
+        });
+        equity == self.computed_equity(as_of)
+    }
+
+    /// Statement of changes in equity, giving each equity account's opening balance, net change over the
+    /// range (inclusive) and closing balance.
+    pub fn equity_statement(&self, from: NaiveDate, to: NaiveDate) -> Vec<(Account, Decimal, Decimal, Decimal)> {
+        let day_before = from.pred_opt().unwrap_or(from);
+        self.accounts()
+            .into_iter()
+            .filter(|a| matches!(a.account_type, AccountType::Equity))
+            .map(|a| {
+                let opening = self.balance_as_of(a.id, day_before).unwrap();
+                let closing = self.balance_as_of(a.id, to).unwrap();
+                (a, opening, closing - opening, closing)
+            })
+            .collect()
+    }
+
+    /// Net change in an Account's balance from its entries dated within the range (inclusive), relative to
+    /// its normal balance. The starting balance isn't included.
+    pub fn net_change(&self, account_id: Uuid, from: NaiveDate, to: NaiveDate) -> Result<Decimal, BooksError> {
+        let account = match self.accounts.get(&account_id) {
+            Some(a) => a,
+            None => return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str())),
+        };
+        let normal_balance = account.normal_balance();
+        Ok(self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t))
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.account_id == account_id && e.date >= from && e.date <= to && account.after_starting_balance(e.date))
+            .fold(Decimal::ZERO, |total, e| total + e.signed_amount(normal_balance)))
+    }
+
+    /// Net change in an Account's balance for each month in the date range (inclusive), as (year, month,
+    /// net change). Months without any entries report zero.
+    pub fn monthly_net(&self, account_id: Uuid, from: NaiveDate, to: NaiveDate) -> Vec<(i32, u32, Decimal)> {
+        let account = match self.accounts.get(&account_id) {
+            Some(a) => a,
+            None => return Vec::new(),
+        };
+        let mut months: BTreeMap<(i32, u32), Decimal> = BTreeMap::new();
+        let mut month = NaiveDate::from_ymd_opt(from.year(), from.month(), 1).unwrap();
+        while month <= to {
+            months.insert((month.year(), month.month()), Decimal::ZERO);
+            month = ScheduleEnum::Months.shift(month, 1);
+        }
+
+        self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t))
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.account_id == account_id && e.date >= from && e.date <= to && account.after_starting_balance(e.date))
+            .for_each(|e| *months.entry((e.date.year(), e.date.month())).or_default() += e.signed_amount(account.normal_balance()));
+
+        months.into_iter().map(|((year, month), net)| (year, month, net)).collect()
+    }
+
+    /// Average monthly net activity of an Account, e.g. spend on an expense account, over the given number
+    /// of months up to and including the month of the ending date. Only entries up to the ending date count.
+    pub fn average_monthly_spend(&self, account_id: Uuid, months: u32, ending: NaiveDate) -> Result<Decimal, BooksError> {
+        if !self.accounts.contains_key(&account_id) {
+            return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str()));
+        }
+        if months < 1 {
+            return Err(BooksError::from_str("Average must be over at least one month"));
+        }
+        let first_month = NaiveDate::from_ymd_opt(ending.year(), ending.month(), 1).unwrap();
+        let from = shift_months(first_month, -((months - 1) as i32));
+        let total: Decimal = self.monthly_net(account_id, from, ending).iter().map(|(_, _, net)| net).sum();
+        Ok(total / Decimal::from(months))
+    }
+
+    /// Balance of an Account at the end of each month, for the month ends within the date range (inclusive).
+    pub fn month_end_balances(&self, account_id: Uuid, from: NaiveDate, to: NaiveDate) -> Vec<(NaiveDate, Decimal)> {
+        let mut balances = Vec::new();
+        let mut next_month = ScheduleEnum::Months.shift(NaiveDate::from_ymd_opt(from.year(), from.month(), 1).unwrap(), 1);
+        while let Some(month_end) = next_month.pred_opt().filter(|d| *d <= to) {
+            match self.balance_as_of(account_id, month_end) {
+                Ok(balance) => balances.push((month_end, balance)),
+                Err(_) => return Vec::new(),
+            }
+            next_month = ScheduleEnum::Months.shift(next_month, 1);
+        }
+        balances
+    }
+
+    /// Percentage of an Account's monthly budget used in the month containing the given date, i.e. the
+    /// account's net change over the month divided by its budget.
+    pub fn budget_utilization(&self, account_id: Uuid, month: NaiveDate) -> Result<Decimal, BooksError> {
+        let account = match self.accounts.get(&account_id) {
+            Some(a) => a,
+            None => return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str())),
+        };
+        let budget = match account.monthly_budget {
+            Some(b) if b.is_zero() => return Err(BooksError::from_str(format!("Account {} has a budget of zero", account.name).as_str())),
+            Some(b) => b,
+            None => return Err(BooksError::from_str(format!("Account {} has no budget", account.name).as_str())),
+        };
+        let first = NaiveDate::from_ymd_opt(month.year(), month.month(), 1).unwrap();
+        let last = ScheduleEnum::Months.shift(first, 1).pred_opt().unwrap();
+        let spent = self.balance_as_of(account_id, last)? - self.balance_as_of(account_id, first.pred_opt().unwrap_or(first))?;
+        Ok(spent / budget * Decimal::ONE_HUNDRED)
+    }
+
+    /// Statement for an Account over a date range (inclusive) as JSON, with the opening balance, the entries
+    /// in the range with their running balances and the closing balance.
+    pub fn account_statement_json(&self, account_id: Uuid, from: NaiveDate, to: NaiveDate) -> Result<String, BooksError> {
+        let entries: Vec<Entry> = self.account_entries(account_id)?
+            .into_iter()
+            .filter(|e| e.date >= from && e.date <= to)
+            .collect();
+        let statement = AccountStatement {
+            account: self.accounts[&account_id].clone(),
+            from,
+            to,
+            opening_balance: self.balance_as_of(account_id, from.pred_opt().unwrap_or(from))?,
+            entries,
+            closing_balance: self.balance_as_of(account_id, to)?,
+        };
+        serde_json::to_string(&statement).map_err(|e| BooksError{ error: format!("Failed to write statement: {}", e) })
+    }
+
+    /// Trial balance as of the given date, with the total debits and credits of each account's entries
+    /// dated on or before it and the resulting balance. Rows are in the same order as `accounts()`.
+    pub fn trial_balance(&self, as_of: NaiveDate) -> Vec<TrialBalanceRow> {
+        let mut totals: HashMap<Uuid, (Decimal, Decimal)> = HashMap::new();
+        self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t))
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.date <= as_of && self.accounts.get(&e.account_id).is_some_and(|a| a.after_starting_balance(e.date)))
+            .for_each(|e| {
+                let (debits, credits) = totals.entry(e.account_id).or_default();
+                match e.entry_type {
+                    Side::Debit => *debits += e.amount,
+                    Side::Credit => *credits += e.amount,
+                }
+            });
+
+        self.accounts()
+            .into_iter()
+            .map(|a| {
+                let (debit_total, credit_total) = totals.get(&a.id).copied().unwrap_or_default();
+                let balance = match a.normal_balance() {
+                    Side::Debit => a.starting_balance + debit_total - credit_total,
+                    Side::Credit => a.starting_balance + credit_total - debit_total,
+                };
+                TrialBalanceRow { account_id: a.id, name: a.name, account_type: a.account_type, debit_total, credit_total, balance }
+            })
+            .collect()
+    }
+
+    /// Totals of the entries tagged with the dimension, e.g. "project", dated within the range (inclusive),
+    /// keyed by the dimension's value. Amounts are signed by their account's normal balance, so an expense
+    /// and its refund net off. Untagged entries and entries excluded from reports are left out.
+    pub fn report_by_dimension(&self, key: &str, from: NaiveDate, to: NaiveDate) -> HashMap<String, Decimal> {
+        let mut totals: HashMap<String, Decimal> = HashMap::new();
+        self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t))
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.date >= from && e.date <= to && !e.exclude_from_reports)
+            .for_each(|e| {
+                if let (Some(value), Some(account)) = (e.dimensions.get(key), self.accounts.get(&e.account_id)) {
+                    *totals.entry(value.clone()).or_default() += e.signed_amount(account.normal_balance());
+                }
+            });
+        totals
+    }
+
+    /// Income statement for the date range (inclusive) from recorded transactions. Closing transactions
+    /// are left out so closed periods still report their income, as are entries excluded from reports.
+    pub fn income_statement(&self, start: NaiveDate, end: NaiveDate) -> IncomeStatement {
+        self.income_statement_with_projections(start, end, false)
+    }
+
+    /// Income statement for the date range (inclusive), optionally including projected transactions, e.g.
+    /// for a forecast.
+    pub fn income_statement_with_projections(&self, start: NaiveDate, end: NaiveDate, include_projected: bool) -> IncomeStatement {
+        let mut activity: HashMap<Uuid, Decimal> = HashMap::new();
+        self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t) && t.kind != TransactionKind::Closing)
+            .filter(|t| include_projected || t.status != TransactionStatus::Projected)
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.date >= start && e.date <= end && !e.exclude_from_reports)
+            .for_each(|e| {
+                if let Some(account) = self.accounts.get(&e.account_id) {
+                    *activity.entry(e.account_id).or_default() += e.signed_amount(account.normal_balance());
+                }
+            });
+
+        let mut statement = IncomeStatement {
+            start,
+            end,
+            accounts: Vec::new(),
+            total_revenue: Decimal::ZERO,
+            total_expense: Decimal::ZERO,
+            net_income: Decimal::ZERO,
+        };
+        for a in self.accounts().into_iter().filter(|a| a.account_type.is_income_statement()) {
+            let balance = activity.get(&a.id).copied().unwrap_or_default();
+            match a.account_type {
+                AccountType::Revenue => statement.total_revenue += balance,
+                _ => statement.total_expense += balance,
+            }
+            statement.accounts.push(AccountBalance { id: a.id, name: a.name, account_type: a.account_type, balance });
+        }
+        statement.net_income = statement.total_revenue - statement.total_expense;
+        statement
+    }
+
+    /// Balance sheet as of the given date. Every asset, liability and equity account is listed, including
+    /// those without any entries.
+    pub fn balance_sheet(&self, as_of: NaiveDate) -> BalanceSheet {
+        let balances = self.balances_as_of(as_of);
+        let mut sheet = BalanceSheet {
+            as_of,
+            assets: Vec::new(),
+            liabilities: Vec::new(),
+            equity: Vec::new(),
+            net_income: Decimal::ZERO,
+            total_assets: Decimal::ZERO,
+            total_liabilities: Decimal::ZERO,
+            total_equity: Decimal::ZERO,
+            category_totals: Vec::new(),
+        };
+        for a in self.accounts() {
+            let balance = balances[&a.id];
+            if let Some(category) = a.category.as_ref().filter(|_| !a.account_type.is_income_statement()) {
+                match sheet.category_totals.iter_mut().find(|c| c.account_type == a.account_type && c.category == category.name) {
+                    Some(c) => c.total += balance,
+                    None => sheet.category_totals.push(CategoryTotal { account_type: a.account_type.clone(), category: category.name.clone(), total: balance }),
+                }
+            }
+            let (accounts, total) = match a.account_type {
+                AccountType::Asset => (&mut sheet.assets, &mut sheet.total_assets),
+                AccountType::Liability => (&mut sheet.liabilities, &mut sheet.total_liabilities),
+                AccountType::Equity => (&mut sheet.equity, &mut sheet.total_equity),
+                AccountType::Revenue => {
+                    sheet.net_income += balance;
+                    continue;
+                },
+                AccountType::Expense => {
+                    sheet.net_income -= balance;
+                    continue;
+                },
+            };
+            *total += balance;
+            accounts.push(AccountBalance { id: a.id, name: a.name, account_type: a.account_type, balance });
+        }
+        sheet.total_equity += sheet.net_income;
+        sheet
+    }
+
+    /// Minimal snapshot of account balances as of the given date, for syncing read only displays.
+    pub fn balances_snapshot(&self, as_of: NaiveDate) -> BalancesSnapshot {
+        let balances = self.balances_as_of(as_of);
+        BalancesSnapshot {
+            as_of,
+            accounts: self.accounts()
+                .into_iter()
+                .map(|a| AccountBalance { id: a.id, balance: balances[&a.id], name: a.name, account_type: a.account_type })
+                .collect(),
+        }
+    }
+
+    /// How far the session's statement balance is from the account's starting balance plus the cleared
+    /// entries of posted transactions, i.e. zero once the reconciliation is complete. Errors if the session's
+    /// account isn't in these books, as a session is built from an account id alone and there is then no
+    /// starting balance or normal side to reconcile against.
+    pub fn reconciliation_difference(&self, session: &ReconciliationSession) -> Result<Decimal, BooksError> {
+        let account = match self.accounts.get(&session.account_id) {
+            Some(a) => a,
+            None => return Err(BooksError::from_str(format!("Account not found for id {}", session.account_id).as_str())),
+        };
+        let cleared_balance = self.transactions
+            .iter()
+            .filter(|t| self.is_posted(t))
+            .flat_map(|t| t.entries.iter())
+            .filter(|e| e.account_id == account.id && e.date <= session.statement_date && session.cleared.contains(&e.id)
+                && account.after_starting_balance(e.date))
+            .fold(account.starting_balance, |balance, e| balance + e.signed_amount(account.normal_balance()));
+        Ok(session.target_balance - cleared_balance)
+    }
+
+    /// Balances of all Accounts as of the given date, computed in a single pass over the transactions.
+    pub fn balances_as_of(&self, date: NaiveDate) -> HashMap<Uuid, Decimal> {
+        let mut balances: HashMap<Uuid, Decimal> = self.accounts
+            .values()
+            .map(|a| (a.id, a.starting_balance))
+            .collect();
+
+        for e in self.transactions.iter().filter(|t| self.is_posted(t)).flat_map(|t| t.entries.iter()) {
+            if e.date > date {
+                continue;
+            }
+            if let (Some(account), Some(balance)) = (self.accounts.get(&e.account_id), balances.get_mut(&e.account_id)) {
+                if account.after_starting_balance(e.date) {
+                    *balance += e.signed_amount(account.normal_balance());
+                }
+            }
+        }
+        balances
+    }
+
+    /// Sum of the balances of all Accounts of the given type as of the given date.
+    pub fn total_by_type(&self, account_type: AccountType, as_of: NaiveDate) -> Decimal {
+        let balances = self.balances_as_of(as_of);
+        self.accounts
+            .values()
+            .filter(|a| a.account_type == account_type)
+            .filter_map(|a| balances.get(&a.id))
+            .sum()
+    }
+
+
+    pub fn add_schedule(&mut self, schedule: Schedule) -> Result<(), BooksError> {
+        if let Some(value) = self.validate_schedule(&schedule) {
+            return value;
+        }
+
+        self.scheduler.add_schedule(schedule);
+        Ok(())
+    }
+
+    fn validate_schedule(&mut self, schedule: &Schedule) -> Option<Result<(), BooksError>> {
+
+        if schedule.entries.len() < 1 {
             return Some(Err(BooksError::from_str("A schedule must have at least one transaction entry")))
         }
 
-        for e in schedule.entries.iter() {
-            if !self.valid_account_id(Some(e.account_id)) {
-                return Some(Err(BooksError::from_str(format!("Invalid account: {}", e.account_id).as_str())))
-            }
-        }
+        for e in schedule.entries.iter() {
+            if !self.valid_account_id(Some(e.account_id)) {
+                return Some(Err(BooksError::from_str(format!("Invalid account: {}", e.account_id).as_str())))
+            }
+        }
+
+        if let Some(modifier) = &schedule.modifier {
+            if modifier.frequency < 1 {
+                return Some(Err(BooksError::from_str(format!("Modifier frequency must be at least 1, got {}", modifier.frequency).as_str())))
+            }
+            if schedule.end_date.is_some_and(|end_date| modifier.start_date > end_date) {
+                return Some(Err(BooksError::from_str(
+                    format!("Modifier start date {} is after the schedule end date {}", modifier.start_date, schedule.end_date.unwrap()).as_str())))
+            }
+            match &modifier.kind {
+                ModifierKind::PercentOfAccountBalance { account_id, .. } => if !self.valid_account_id(Some(*account_id)) {
+                    return Some(Err(BooksError::from_str(format!("Invalid modifier account: {}", account_id).as_str())))
+                }
+                ModifierKind::StraightLine { periods, .. } => if *periods < 1 {
+                    return Some(Err(BooksError::from_str("Straight line periods must be at least 1")))
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn update_schedule(&mut self, schedule: Schedule) -> Result<(), BooksError> {
+        if let Some(value) = self.validate_schedule(&schedule) {
+            return value;
+        }
+
+        self.scheduler.update_schedule(schedule)
+    }
+
+    /// Delete a schedule so it generates no more transactions. When `remove_projected` is set, projected
+    /// transactions it already generated are removed too; recorded ones are always kept.
+    pub fn delete_schedule(&mut self, id: &Uuid, remove_projected: bool) -> Result<(), BooksError> {
+        self.scheduler.delete_schedule(id)?;
+        if remove_projected {
+            self.transactions.retain(|t| !(t.status == TransactionStatus::Projected && t.schedule_id() == Some(*id)));
+            self.date_index.take();
+        }
+        Ok(())
+    }
+
+    /// Update only the given fields of a schedule, leaving its entries untouched. Pass `Some(None)` as the
+    /// end date to clear it.
+    pub fn patch_schedule(&mut self, id: Uuid, name: Option<String>, active: Option<bool>, end_date: Option<Option<NaiveDate>>) -> Result<(), BooksError> {
+        let mut schedule = match self.scheduler.schedules().iter().find(|s| s.id == id) {
+            Some(s) => s.clone(),
+            None => return Err(BooksError::from_str("Schedule not found")),
+        };
+        if let Some(name) = name {
+            schedule.name = name;
+        }
+        if let Some(active) = active {
+            schedule.active = active;
+        }
+        if let Some(end_date) = end_date {
+            schedule.end_date = end_date;
+        }
+        self.update_schedule(schedule)
+    }
+
+    /// A schedule as JSON, e.g. to share with other books using `import_schedule`.
+    pub fn export_schedule(&self, id: Uuid) -> Result<String, BooksError> {
+        let schedule = match self.scheduler.schedules().iter().find(|s| s.id == id) {
+            Some(s) => s,
+            None => return Err(BooksError::from_str("Schedule not found")),
+        };
+        serde_json::to_string(schedule).map_err(|e| BooksError{ error: format!("Failed to write schedule: {}", e) })
+    }
+
+    /// Add a schedule exported from other books, with a new id. Account ids are replaced using the mapping
+    /// from the other books' ids to these books' ids, ids not in the mapping are kept. The schedule starts
+    /// generating again from its start date. Returns the new schedule's id.
+    pub fn import_schedule(&mut self, json: &str, account_mapping: HashMap<Uuid, Uuid>) -> Result<Uuid, BooksError> {
+        let mut schedule: Schedule = serde_json::from_str(json)
+            .map_err(|e| BooksError{ error: format!("Failed to read schedule: {}", e) })?;
+        let map = |id: &mut Uuid| if let Some(mapped) = account_mapping.get(id) { *id = *mapped };
+        schedule.id = Uuid::new_v4();
+        schedule.reset();
+        for e in schedule.entries.iter_mut() {
+            e.schedule_id = schedule.id;
+            map(&mut e.account_id);
+        }
+        if let Some(modifier) = schedule.modifier.as_mut() {
+            match &mut modifier.kind {
+                ModifierKind::PercentOfAccountBalance { account_id, .. } => map(account_id),
+                ModifierKind::StraightLine { .. } => (),
+            }
+        }
+        let schedule_id = schedule.id;
+        self.add_schedule(schedule)?;
+        Ok(schedule_id)
+    }
+
+    pub fn schedules(&self) -> &[Schedule] {
+        self.scheduler.schedules()
+    }
+
+    pub fn end_date(&self) -> Option<NaiveDate> {
+        self.scheduler.end_date()
+    }
+
+    /// Occurrence dates of a schedule, up to the given date, that have been generated (i.e. are on or before
+    /// the schedule's last date) but no longer have a transaction, e.g. because it was deleted. Dates are
+    /// after any business day adjustment.
+    pub fn missing_schedule_occurrences(&self, schedule_id: Uuid, through: NaiveDate) -> Vec<NaiveDate> {
+        let schedule = match self.scheduler.schedules().iter().find(|s| s.id == schedule_id) {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        let last_date = match schedule.last_date {
+            Some(d) => d.min(through),
+            None => return Vec::new(),
+        };
+        let present: Vec<NaiveDate> = self.transactions
+            .iter()
+            .filter(|t| t.schedule_id() == Some(schedule_id))
+            .filter_map(|t| t.date())
+            .collect();
+
+        schedule.occurrences_through(last_date)
+            .into_iter()
+            .map(|d| schedule.adjust_date(d))
+            .filter(|d| !present.contains(d))
+            .collect()
+    }
+
+    /// Copies of the schedules with an entry, or a modifier, referencing the account.
+    pub fn schedules_affecting(&self, account_id: Uuid) -> Vec<Schedule> {
+        self.scheduler.schedules()
+            .iter()
+            .filter(|s| s.involves_account(&account_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Days from the given date to the schedule's next occurrence, negative if it is overdue. None if the
+    /// schedule isn't found or won't occur again.
+    pub fn days_until_next(&self, schedule_id: Uuid, from: NaiveDate) -> Option<i64> {
+        let schedule = self.scheduler.schedules().iter().find(|s| s.id == schedule_id)?;
+        let next = schedule.next_posting_date()?;
+        Some((next - from).num_days())
+    }
+
+    /// Copies of the schedules paired with their next occurrence date, soonest first. Schedules that won't
+    /// occur again, paired with the date they would have next occurred, are sorted last.
+    pub fn schedules_by_next_date(&self) -> Vec<(Schedule, NaiveDate)> {
+        let mut schedules: Vec<(Schedule, Option<NaiveDate>)> = self.scheduler.schedules()
+            .iter()
+            .map(|s| (s.clone(), s.next_occurrence(NaiveDate::MAX)))
+            .collect();
+        schedules.sort_by_key(|(s, next)| (next.is_none(), next.unwrap_or(s.get_next_date())));
+        schedules.into_iter()
+            .map(|(s, next)| {
+                let date = next.unwrap_or(s.get_next_date());
+                (s, date)
+            })
+            .collect()
+    }
+
+    /// Schedules that can never generate another transaction, with the reason why.
+    pub fn inactive_schedules(&self) -> Vec<(Uuid, String)> {
+        self.scheduler.schedules()
+            .iter()
+            .filter_map(|s| s.inactive_reason().map(|reason| (s.id, reason)))
+            .collect()
+    }
+
+    /// Re-point entries referencing accounts that don't exist, e.g. after manual edits, to the fallback
+    /// account. Returns the number of entries repaired, nothing is changed if the fallback doesn't exist.
+    pub fn repair_dangling_entries(&mut self, fallback_account: Uuid) -> usize {
+        if !self.accounts.contains_key(&fallback_account) {
+            return 0
+        }
+        let mut repaired = 0;
+        for e in self.transactions.iter_mut().flat_map(|t| t.entries.iter_mut()) {
+            if !self.accounts.contains_key(&e.account_id) {
+                e.account_id = fallback_account;
+                repaired += 1;
+            }
+        }
+        repaired
+    }
+
+    /// Set every stored amount to the given number of decimal places, e.g. so saved JSON shows amounts
+    /// consistently. Fails without changing anything if an amount has more decimal places, as rescaling it
+    /// would lose precision.
+    pub fn rescale_amounts(&mut self, scale: u32) -> Result<(), BooksError> {
+        let mut amounts = self.amounts_mut();
+        if let Some(a) = amounts.iter().find(|a| a.normalize().scale() > scale) {
+            return Err(BooksError::from_str(format!("Amount {} has more than {} decimal places", a, scale).as_str()))
+        }
+        amounts.iter_mut().for_each(|a| a.rescale(scale));
+        Ok(())
+    }
+
+    fn amounts_mut(&mut self) -> Vec<&mut Decimal> {
+        let mut amounts: Vec<&mut Decimal> = Vec::new();
+        for a in self.accounts.values_mut() {
+            amounts.push(&mut a.balance);
+            amounts.push(&mut a.starting_balance);
+            amounts.extend(a.monthly_budget.iter_mut());
+        }
+        for e in self.transactions.iter_mut().flat_map(|t| t.entries.iter_mut()) {
+            amounts.push(&mut e.amount);
+            amounts.extend(e.balance.iter_mut());
+            amounts.extend(e.foreign_amount.iter_mut());
+        }
+        for e in self.scheduler.schedules_mut().iter_mut().flat_map(|s| s.entries.iter_mut()) {
+            amounts.push(&mut e.amount);
+            amounts.extend(e.step_schedule.steps.iter_mut().map(|s| &mut s.amount));
+        }
+        amounts
+    }
+
+    fn valid_account_id(&self, id: Option<Uuid>) -> bool {
+        match id {
+            Some(k) => return self.accounts.contains_key(&k),
+            None => return true
+        }
+    }
+}
+
+/// What `Books::generate` did.
+#[derive(Debug, Default, PartialEq)]
+pub struct GenerateReport {
+    /// Schedules skipped because an account they reference doesn't exist, with the reason.
+    pub skipped: Vec<(Uuid, String)>,
+}
+
+#[derive(Serialize)]
+pub struct AccountStatement {
+    pub account: Account,
+    #[serde(serialize_with = "serialize_naivedate")]
+    pub from: NaiveDate,
+    #[serde(serialize_with = "serialize_naivedate")]
+    pub to: NaiveDate,
+    pub opening_balance: Decimal,
+    pub entries: Vec<Entry>,
+    pub closing_balance: Decimal,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccountBalance {
+    pub id: Uuid,
+    pub name: String,
+    pub account_type: AccountType,
+    pub balance: Decimal,
+}
+
+/// An account's line in the trial balance. The balance includes the starting balance.
+#[derive(Serialize, Debug)]
+pub struct TrialBalanceRow {
+    pub account_id: Uuid,
+    pub name: String,
+    pub account_type: AccountType,
+    pub debit_total: Decimal,
+    pub credit_total: Decimal,
+    pub balance: Decimal,
+}
+
+/// Revenue and expenses over a date range. Each account's amount is its net activity in the range.
+#[derive(Serialize)]
+pub struct IncomeStatement {
+    #[serde(serialize_with = "serialize_naivedate")]
+    pub start: NaiveDate,
+    #[serde(serialize_with = "serialize_naivedate")]
+    pub end: NaiveDate,
+    pub accounts: Vec<AccountBalance>,
+    pub total_revenue: Decimal,
+    pub total_expense: Decimal,
+    pub net_income: Decimal,
+}
+
+/// Balances of the asset, liability and equity accounts at a date. Revenue less expenses not yet closed
+/// to equity is included in equity as net income, so assets equal liabilities plus equity.
+#[derive(Serialize)]
+pub struct BalanceSheet {
+    #[serde(serialize_with = "serialize_naivedate")]
+    pub as_of: NaiveDate,
+    pub assets: Vec<AccountBalance>,
+    pub liabilities: Vec<AccountBalance>,
+    pub equity: Vec<AccountBalance>,
+    pub net_income: Decimal,
+    pub total_assets: Decimal,
+    pub total_liabilities: Decimal,
+    pub total_equity: Decimal,
+    /// Subtotals of the categorised accounts, in the order the categories first appear.
+    pub category_totals: Vec<CategoryTotal>,
+}
+
+#[derive(Serialize)]
+pub struct CategoryTotal {
+    pub account_type: AccountType,
+    pub category: String,
+    pub total: Decimal,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BalancesSnapshot {
+    #[serde(serialize_with = "serialize_naivedate")]
+    #[serde(deserialize_with = "deserialize_naivedate")]
+    pub as_of: NaiveDate,
+    pub accounts: Vec<AccountBalance>,
+}
+
+/// Transactions are kept in date order, so sort them when loading. The sort is stable so transactions on the
+/// same date keep their saved order.
+fn deserialize_sorted_transactions<'de, D>(deserializer: D) -> Result<Vec<Transaction>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut transactions = Vec::<Transaction>::deserialize(deserializer)?;
+    transactions.sort_by_key(|t| t.date());
+    Ok(transactions)
+}
+
+fn currency_name(currency: &str) -> &str {
+    if currency.is_empty() { "(none)" } else { currency }
+}
+
+#[derive(Debug)]
+pub struct BooksError {
+    pub error: String,
+}
+
+impl BooksError {
+    pub fn from_str(name: &str) -> BooksError {
+        BooksError { error: String::from(name) }
+    }
+}
+
+#[cfg(test)]
+
+mod tests {
+    use std::collections::HashMap;
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+    use chrono::{NaiveDate};
+    use rust_decimal_macros::dec;
+    use crate::{account::*, books::{BooksError, RoundingStrategy, REFERENCE_DIMENSION, RETAINED_EARNINGS}, calendar::BusinessDayAdjustment, reconciliation::ReconciliationSession};
+
+    use super::Books;
+
+    #[test]
+    fn test_add_account(){
+        let a = Account::create_new("test account", AccountType::Liability);
+        let id1 = a.id;
+        let mut b = Books::build_empty("My Books");
+        b.add_account(a).unwrap();
+
+        let a2 = &b.accounts()[0];
+        assert_eq!(id1, a2.id);
+    }
+
+    #[test]
+    fn test_create_account(){
+        let mut books = Books::build_empty("My Books");
+        assert_eq!(None, books.create_account("Cash", AccountType::Asset).unwrap().currency);
+
+        books.settings.default_currency = "GBP".to_string();
+        let account = books.create_account("Savings", AccountType::Asset).unwrap();
+
+        assert_eq!(Some("GBP".to_string()), account.currency);
+        assert_eq!(Some("GBP".to_string()), books.accounts[&account.id].currency);
+        assert_eq!("Savings", books.accounts[&account.id].name);
+    }
+
+    #[test]
+    fn test_mixed_currencies() {
+        let (mut books, usd_id, eur_id) = setup_books();
+        books.settings.default_currency = "USD".to_string();
+        books.accounts.get_mut(&eur_id).unwrap().currency = Some("EUR".to_string());
+        assert_eq!(Some("USD".to_string()), books.account_currency(&usd_id));
+        assert_eq!(Some("EUR".to_string()), books.account_currency(&eur_id));
+
+        let transfer = build_transaction(Some(usd_id), Some(eur_id));
+        assert_eq!("Transaction mixes currencies USD and EUR without an exchange entry", books.add_transaction(transfer.clone()).err().unwrap().error);
+
+        let mut exchange = transfer;
+        exchange.entries[1].currency = Some("EUR".to_string());
+        exchange.entries[1].foreign_amount = Some(dec!(9000));
+        books.add_transaction(exchange).unwrap();
+    }
+
+    #[test]
+    fn test_balance_in_currency() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.default_currency = "EUR".to_string();
+        books.add_transaction(build_transaction(Some(id1), Some(id2))).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+        let rate = |from: &str, to: &str, _date: NaiveDate| match (from, to) {
+            ("EUR", "USD") => Some(dec!(1.05)),
+            _ => None,
+        };
+
+        assert_eq!(dec!(10000), books.balance_in_currency(id1, date, "EUR", rate).unwrap());
+        assert_eq!(dec!(10500), books.balance_in_currency(id1, date, "USD", rate).unwrap());
+        assert_eq!("No exchange rate from EUR to GBP on 2022-06-30", books.balance_in_currency(id1, date, "GBP", rate).err().unwrap().error);
+        books.settings.default_currency = String::new();
+        assert!(books.balance_in_currency(id1, date, "USD", rate).is_err());
+    }
+
+    #[test]
+    fn test_net_worth() {
+        let (mut books, usd_id, eur_id) = setup_books();
+        books.settings.default_currency = "USD".to_string();
+        books.accounts.get_mut(&usd_id).unwrap().starting_balance = dec!(1000);
+        let eur = books.accounts.get_mut(&eur_id).unwrap();
+        eur.currency = Some("EUR".to_string());
+        eur.starting_balance = dec!(200);
+        let mut card = Account::create_new("Card", AccountType::Liability);
+        card.starting_balance = dec!(150);
+        books.add_account(card).unwrap();
+        let mut salary = Account::create_new("Salary", AccountType::Revenue);
+        salary.starting_balance = dec!(5000);
+        books.add_account(salary).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+
+        let rates = |from: &str, to: &str| match (from, to) {
+            ("EUR", "USD") => Some(dec!(1.1)),
+            _ => None,
+        };
+        assert_eq!(dec!(1070), books.net_worth(date, &rates).unwrap());
+
+        books.accounts.get_mut(&eur_id).unwrap().currency = Some("GBP".to_string());
+        assert_eq!("No exchange rate from GBP to USD for account Savings Account 2", books.net_worth(date, &rates).err().unwrap().error);
+    }
+
+    #[test]
+    fn test_accounts_locked(){
+        let (mut books, id1, _) = setup_books();
+        books.settings.accounts_locked = true;
+
+        let result = books.add_account(Account::create_new("New account", AccountType::Asset));
+        assert_eq!("Accounts are locked, unlock them to add new accounts.", result.err().unwrap().error);
+        assert_eq!(2, books.accounts().len());
+
+        let mut existing = books.accounts[&id1].clone();
+        existing.name = "Renamed".to_string();
+        books.add_account(existing).unwrap();
+        assert_eq!("Renamed", books.accounts[&id1].name);
+
+        books.settings.accounts_locked = false;
+        books.add_account(Account::create_new("New account", AccountType::Asset)).unwrap();
+        assert_eq!(3, books.accounts().len());
+    }
+
+    #[test]
+    fn test_account_codes() {
+        let (mut books, id1, id2) = setup_books();
+        let mut revenue = Account::create_new("Sales", AccountType::Revenue);
+        revenue.code = Some("4000".to_string());
+        let revenue_id = revenue.id;
+        books.add_account(revenue).unwrap();
+        books.set_account_code(&id2, Some("1000".to_string())).unwrap();
+
+        let order: Vec<Uuid> = books.accounts().iter().map(|a| a.id).collect();
+        assert_eq!(vec![id2, revenue_id, id1], order);
+        assert_eq!(Some(revenue_id), books.account_by_code("4000").map(|a| a.id));
+        assert!(books.account_by_code("9999").is_none());
+
+        assert_eq!("Account code 1000 is already used by Savings Account 2.", books.set_account_code(&id1, Some("1000".to_string())).err().unwrap().error);
+        let mut duplicate = Account::create_new("Other sales", AccountType::Revenue);
+        duplicate.code = Some("4000".to_string());
+        assert!(books.add_account(duplicate).is_err());
+        // Re-adding an account with its own code is an update, not a collision.
+        let existing = books.account_by_code("4000").unwrap();
+        books.add_account(existing).unwrap();
+
+        books.set_account_code(&id2, None).unwrap();
+        assert_eq!(vec![revenue_id, id1, id2], books.accounts().iter().map(|a| a.id).collect::<Vec<Uuid>>());
+    }
+
+    #[test]
+    fn test_archive_account() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction(Some(id1), Some(id2))).unwrap();
+
+        books.archive_account(&id1).unwrap();
+        assert_eq!(2, books.accounts().len());
+        assert_eq!(vec![id2], books.active_accounts().iter().map(|a| a.id).collect::<Vec<Uuid>>());
+
+        books.unarchive_account(&id1).unwrap();
+        assert_eq!(2, books.active_accounts().len());
+        let missing = Uuid::new_v4();
+        assert_eq!(format!("Account {} not found.", missing), books.archive_account(&missing).err().unwrap().error);
+    }
+
+    #[test]
+    fn test_delete_account(){
+        let (mut books, id1, id2) = setup_books();
+        let _result = books.delete_account(&id1);
+        assert!(matches!((), _result));
+        assert!(books.accounts.get(&id1).is_none());
+        assert!(books.accounts.get(&id2).is_some());
+    }
+
+    #[test]
+    fn test_cannot_delete_account_with_transactions(){
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(None, Some(id1));
+        books.add_transaction(t1).unwrap();
+        let result = books.delete_account(&id1);
+        assert_eq!(format!("Account {} can not be deleted as it has transactions.", id1).as_str(), result.err().unwrap().error);
+        assert!(books.accounts.get(&id1).is_some());
+        assert!(books.accounts.get(&id2).is_some());
+    }
+
+    #[test]
+    fn test_cannot_delete_account_with_schedule(){
+        let (mut books, id1, id2) = setup_books();
+        books.add_schedule(build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        let result = books.delete_account(&id1);
+        assert_eq!(format!("Account {} can not be deleted as it is used by a schedule.", id1).as_str(), result.err().unwrap().error);
+        assert!(books.accounts.contains_key(&id1));
+    }
+
+    #[test]
+    fn test_is_account_used(){
+        let (mut books, id1, id2) = setup_books();
+        let a3 = Account::create_new("Savings Account 3", AccountType::Asset);
+        let id3 = a3.id;
+        books.add_account(a3).unwrap();
+        assert!(!books.is_account_used(id1));
+        assert!(!books.is_account_used(id2));
+
+        books.add_transaction(build_transaction(Some(id1), None)).unwrap();
+        books.add_schedule(build_schedule_std(id2, id3, NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        assert!(books.is_account_used(id1));
+        assert!(books.is_account_used(id2));
+        assert!(books.is_account_used(id3));
+        assert!(!books.is_account_used(Uuid::new_v4()));
+    }
+
+    #[test]
+    fn test_cannot_delete_with_invalid_account_id(){
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(None, Some(id1));
+        books.add_transaction(t1).unwrap();
+        let id = &Uuid::new_v4();
+        let result = books.delete_account(id);
+        assert_eq!(format!("Account {} not found.", id  ).as_str(), result.err().unwrap().error);
+        assert!(books.accounts.get(&id1).is_some());
+        assert!(books.accounts.get(&id2).is_some());
+    }
+
+    #[test]
+    fn test_add_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(Some(id1), Some(id2));
+        let t1_id = t1.id;
+        books.add_transaction(t1).unwrap();
+        let t1_2 = &books.transactions()[0];
+        assert_eq!(t1_id, t1_2.id);
+    }
+
+    #[test]
+    fn test_transactions_sorted_on_insert() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 10));
+        let t2 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let t3 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 7));
+        let t4 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let ids = [t2.id, t4.id, t3.id, t1.id];
+        books.add_transaction(t1).unwrap();
+        books.add_transaction(t2).unwrap();
+        books.add_transaction(t3).unwrap();
+        books.add_transaction(t4).unwrap();
+        assert_eq!(ids.to_vec(), books.transactions().iter().map(|t| t.id).collect::<Vec<Uuid>>());
+
+        let mut moved = books.transactions()[0].clone();
+        moved.entries.iter_mut().for_each(|e| e.date = NaiveDate::from_ymd(2022, 6, 8));
+        books.update_transaction(moved).unwrap();
+        assert_eq!(vec![ids[1], ids[2], ids[0], ids[3]], books.transactions().iter().map(|t| t.id).collect::<Vec<Uuid>>());
+    }
+
+    #[test]
+    fn test_transactions_sorted_on_load() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let t2 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let t3 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 7));
+        let ids = [t1.id, t2.id, t3.id];
+        books.add_transaction(t1).unwrap();
+        books.add_transaction(t2).unwrap();
+        books.add_transaction(t3).unwrap();
+
+        // Saved by an older version that didn't keep transactions in order.
+        let mut json = serde_json::to_value(&books).unwrap();
+        json["transactions"].as_array_mut().unwrap().rotate_left(2);
+        let loaded: Books = serde_json::from_value(json).unwrap();
+
+        assert_eq!(ids.to_vec(), loaded.transactions().iter().map(|t| t.id).collect::<Vec<Uuid>>());
+        let found = loaded.transactions_between(NaiveDate::from_ymd(2022, 6, 5), NaiveDate::from_ymd(2022, 6, 30));
+        assert_eq!(vec![ids[2]], found.iter().map(|t| t.id).collect::<Vec<Uuid>>());
+    }
+
+    #[test]
+    fn test_transactions_between() {
+        let (mut books, id1, id2) = setup_books();
+        let start = NaiveDate::from_ymd(2020, 1, 1);
+        let check = |books: &Books| {
+            for i in 0..100 {
+                let from = start + chrono::Duration::days(i * 15);
+                let to = from + chrono::Duration::days(30);
+                let found = books.transactions_between(from, to);
+                let expected: Vec<Uuid> = books.transactions.iter()
+                    .filter(|t| t.entries[0].date >= from && t.entries[0].date <= to)
+                    .map(|t| t.id)
+                    .collect();
+                assert_eq!(expected, found.iter().map(|t| t.id).collect::<Vec<Uuid>>());
+            }
+        };
+
+        // Query as transactions are added so the index is kept up to date rather than built once at the end.
+        for i in 0..5000 {
+            let date = start + chrono::Duration::days((i * 7919) % 1500);
+            books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), date)).unwrap();
+            if i % 1000 == 0 {
+                check(&books);
+            }
+        }
+        check(&books);
+
+        for i in 0..200 {
+            let mut moved = books.transactions()[i * 20].clone();
+            moved.entries.iter_mut().for_each(|e| e.date = start + chrono::Duration::days((i as i64 * 31) % 1500));
+            books.update_transaction(moved).unwrap();
+            let deleted = books.transactions()[i * 7].id;
+            books.delete_transaction(&deleted).unwrap();
+        }
+        check(&books);
+
+        let first = books.transactions()[0].clone();
+        books.delete_transaction(&first.id).unwrap();
+        let first_date = first.entries[0].date;
+        assert!(books.transactions_between(first_date, first_date).iter().all(|t| t.id != first.id));
+        assert!(books.transactions_between(NaiveDate::from_ymd(2030, 1, 1), NaiveDate::from_ymd(2031, 1, 1)).is_empty());
+        assert!(books.transactions_between(NaiveDate::from_ymd(2031, 1, 1), NaiveDate::from_ymd(2030, 1, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_transactions_between_uses_first_entry_date() {
+        let (mut books, id1, id2) = setup_books();
+        let mut t = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 3, 31));
+        t.entries[1].date = NaiveDate::from_ymd(2022, 4, 1);
+        let id = t.id;
+        books.add_transaction(t).unwrap();
+
+        let first_quarter = books.transactions_between(NaiveDate::from_ymd(2022, 1, 1), NaiveDate::from_ymd(2022, 3, 31));
+        assert_eq!(vec![id], first_quarter.iter().map(|t| t.id).collect::<Vec<Uuid>>());
+        assert!(books.transactions_between(NaiveDate::from_ymd(2022, 4, 1), NaiveDate::from_ymd(2022, 6, 30)).is_empty());
+    }
+
+    #[test]
+    fn test_double_entry_required() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.require_double_entry = true;
+        assert_eq!(0, books.transactions.len());
+        let mut t1 = build_transaction(Some(id1), Some(id2));
+        t1.entries.pop();
+        let result = books.add_transaction(t1);
+        assert_eq!("A transaction needs at least two entries (double entry required is on).".to_string(), result.err().unwrap().error);
+        assert_eq!(0, books.transactions.len());
+    }
+
+    #[test]
+    fn test_double_entry_must_balance() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.require_double_entry = true;
+        let mut t1 = build_transaction(Some(id1), Some(id2));
+        t1.entries[0].amount = dec!(105);
+        t1.entries[1].amount = dec!(100);
+
+        let result = books.add_transaction(t1.clone());
+        assert_eq!("Transaction is out of balance by 5.00 (debits 105.00, credits 100.00)", result.err().unwrap().error);
+        assert_eq!(0, books.transactions.len());
+
+        books.settings.allow_unbalanced = true;
+        books.add_transaction(t1).unwrap();
+        assert_eq!(1, books.transactions.len());
+    }
+
+    #[test]
+    fn test_descriptions_required() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.require_descriptions = true;
+        let mut t1 = build_transaction(Some(id1), Some(id2));
+        t1.entries[1].description = "  ".to_string();
+        let result = books.add_transaction(t1);
+        assert_eq!("Every entry needs a description (descriptions required is on).".to_string(), result.err().unwrap().error);
+        assert_eq!(0, books.transactions.len());
+    }
+
+    #[test]
+    fn test_blank_description_allowed() {
+        let (mut books, id1, id2) = setup_books();
+        let mut t1 = build_transaction(Some(id1), Some(id2));
+        t1.entries[0].description = "".to_string();
+        books.add_transaction(t1).unwrap();
+        assert_eq!(1, books.transactions.len());
+    }
+
+    #[test]
+    fn test_max_description_length() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.max_description_length = Some(15);
+        let t1 = build_transaction(Some(id1), Some(id2));
+        books.add_transaction(t1).unwrap();
+
+        let mut t2 = build_transaction(Some(id1), Some(id2));
+        t2.entries[1].description = "received moneys!".to_string();
+        let result = books.add_transaction(t2);
+        assert_eq!("Description is 16 characters long, the maximum is 15: received moneys!".to_string(), result.err().unwrap().error);
+        assert_eq!(1, books.transactions.len());
+    }
+
+    #[test]
+    fn test_at_least_one_entry_required() {
+        let (mut books, id1, id2) = setup_books();
+        assert_eq!(0, books.transactions.len());
+        let mut t1 = build_transaction(Some(id1), Some(id2));
+        t1.entries.pop();
+        t1.entries.pop();
+        let result = books.add_transaction(t1);
+        assert_eq!("A transaction must have at least one entry".to_string(), result.err().unwrap().error);
+        assert_eq!(0, books.transactions.len());
+    }
+
+
+    #[test]
+    fn test_rounding() {
+        let mut books = Books::build_empty("My Books");
+        assert_eq!(RoundingStrategy::HalfEven, books.settings.rounding);
+        assert_eq!(dec!(0.00), books.settings.round(dec!(0.005)));
+        assert_eq!(dec!(0.02), books.settings.round(dec!(0.015)));
+        books.settings.rounding = RoundingStrategy::HalfUp;
+        assert_eq!(dec!(0.01), books.settings.round(dec!(0.005)));
+        books.settings.rounding = RoundingStrategy::Down;
+        assert_eq!(dec!(0.01), books.settings.round(dec!(0.019)));
+
+        let settings: super::Settings = serde_json::from_str(r#"{"require_double_entry":false,"rounding":"HalfUp"}"#).unwrap();
+        assert_eq!(RoundingStrategy::HalfUp, settings.rounding);
+    }
+
+    #[test]
+    fn test_add_transaction_no_cr_account() {
+        let (mut books, id1, _) = setup_books();
+        let t1 = build_transaction(Some(id1), None);
+        let t1_id = t1.id;
+        books.add_transaction(t1).unwrap();
+        let t1_2 = &books.transactions()[0];
+        assert_eq!(t1_id, t1_2.id);
+    }
+
+    #[test]
+    fn test_add_transaction_no_dr_account() {
+        let (mut books, _, id2) = setup_books();
+        let t1 = build_transaction(None, Some(id2));
+        let t1_id = t1.id;
+        books.add_transaction(t1).unwrap();
+        let t1_2 = &books.transactions()[0];
+        assert_eq!(t1_id, t1_2.id);
+    }
+
+    #[test]
+    fn test_add_transaction_invalid_dr_account() {
+        let (mut books, _, id2) = setup_books();
+        let missing_id = Uuid::new_v4();
+        let t1 = build_transaction(Some(missing_id), Some(id2));
+        let result = books.add_transaction(t1);
+        assert_eq!(format!("Account not found for id: {}", missing_id), result.err().unwrap().error);
+        assert_eq!(0, (&books.transactions()).len());
+    }
+
+    #[test]
+    fn test_add_transaction_invalid_cr_account() {
+        let (mut books, id1, _) = setup_books();
+        let missing_id = Uuid::new_v4();
+        let t1 = build_transaction(Some(id1), Some(missing_id));
+        let result = books.add_transaction(t1);
+        assert_eq!(format!("Account not found for id: {}", missing_id), result.err().unwrap().error);
+        assert_eq!(0, (&books.transactions()).len());
+    }
+
+    #[test]
+    fn test_add_transaction_no_account() {
+        let (mut books, _id1, _id2) = setup_books();
+        let t1 = build_transaction(None, None);
+        let result = books.add_transaction(t1.clone());
+        assert_eq!("A transaction must have at least one entry", result.err().unwrap().error);
+        books.settings.require_double_entry = true;
+        let result = books.add_transaction(t1);
+        assert_eq!("A transaction must have at least one entry", result.err().unwrap().error);
+        assert_eq!(0, (&books.transactions()).len());
+    }
+
+    #[test]
+    fn test_entry_transaction_id_must_match() {
+        let (mut books, id1, id2) = setup_books();
+        let mut t = build_transaction(Some(id1), Some(id2));
+        let other_id = Uuid::new_v4();
+        t.entries[1].transaction_id = other_id;
+        let expected = format!("Entry {} belongs to transaction {}, not {}", t.entries[1].id, other_id, t.id);
+
+        let result = books.add_transaction(t.clone());
+        assert_eq!(expected, result.err().unwrap().error);
+
+        t.entries[1].transaction_id = t.id;
+        books.add_transaction(t.clone()).unwrap();
+        t.entries[0].transaction_id = other_id;
+        assert!(books.update_transaction(t).is_err());
+        assert!(books.transactions()[0].entries.iter().all(|e| e.transaction_id == books.transactions()[0].id));
+    }
+
+    #[test]
+    fn test_link_transactions() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(Some(id1), Some(id2));
+        let t1_id = t1.id;
+        books.add_transaction(t1).unwrap();
+        let t2 = build_transaction(Some(id2), Some(id1));
+        let t2_id = t2.id;
+        books.add_transaction(t2).unwrap();
+
+        books.link_transactions(t1_id, t2_id).unwrap();
+        books.link_transactions(t2_id, t1_id).unwrap();
+
+        let related = books.related_transactions(t1_id);
+        assert_eq!(1, related.len());
+        assert_eq!(t2_id, related[0].id);
+        let related = books.related_transactions(t2_id);
+        assert_eq!(1, related.len());
+        assert_eq!(t1_id, related[0].id);
+        assert!(books.link_transactions(t1_id, Uuid::new_v4()).is_err());
+    }
+
+    #[test]
+    fn test_description_suggestions() {
+        let (mut books, id1, id2) = setup_books();
+        for description in ["Rent", "Groceries", "Groceries", "Gym", "groceries", "Gas", "Gas", "Gas"] {
+            let mut t = build_transaction(Some(id1), Some(id2));
+            t.entries.iter_mut().for_each(|e| e.description = description.to_string());
+            books.add_transaction(t).unwrap();
+        }
+
+        assert_eq!(vec!["Gas", "Groceries", "Gym"], books.description_suggestions("g", 3));
+        assert_eq!(vec!["Groceries", "groceries"], books.description_suggestions("GRO", 5));
+        assert_eq!(vec!["Gas"], books.description_suggestions("", 1));
+        assert!(books.description_suggestions("x", 5).is_empty());
+    }
+
+    #[test]
+    fn test_describe_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        let t = build_transaction(Some(id1), Some(id2));
+        let transaction_id = t.id;
+        books.add_transaction(t).unwrap();
+
+        let described = books.describe_transaction(transaction_id).unwrap();
+
+        assert_eq!(vec![
+            ("Savings Account 1".to_string(), Side::Debit, dec!(10000)),
+            ("Savings Account 2".to_string(), Side::Credit, dec!(10000)),
+        ], described);
+        assert!(books.describe_transaction(Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_daybook() {
+        let (mut books, id1, id2) = setup_books();
+        for day in [3, 4, 4, 5] {
+            books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, day))).unwrap();
+        }
+
+        let transactions = books.daybook(NaiveDate::from_ymd(2022, 6, 4));
+
+        assert_eq!(2, transactions.len());
+        assert!(transactions.iter().all(|t| t.date() == Some(NaiveDate::from_ymd(2022, 6, 4))));
+        assert!(books.daybook(NaiveDate::from_ymd(2022, 6, 6)).is_empty());
+    }
+
+    #[test]
+    fn test_transactions_with_amount() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(Some(id1), Some(id2));
+        let t1_id = t1.id;
+        books.add_transaction(t1).unwrap();
+        let mut t2 = build_transaction(Some(id1), Some(id2));
+        t2.entries.iter_mut().for_each(|e| e.amount = dec!(10000.01));
+        let t2_id = t2.id;
+        books.add_transaction(t2).unwrap();
+
+        let exact = books.transactions_with_amount(dec!(-10000));
+        assert_eq!(1, exact.len());
+        assert_eq!(t1_id, exact[0].id);
+
+        let approx = books.transactions_with_amount_approx(dec!(10000), dec!(0.01));
+        assert_eq!(2, approx.len());
+        assert!(approx.iter().any(|t| t.id == t2_id));
+        assert!(books.transactions_with_amount(dec!(5)).is_empty());
+    }
+
+    #[test]
+    fn test_find_unbalanced() {
+        let (mut books, id1, id2) = setup_books();
+        let balanced = build_transaction(Some(id1), Some(id2));
+        let unbalanced = build_transaction(Some(id1), None);
+        let unbalanced_id = unbalanced.id;
+        let mut adjustment = build_transaction(None, Some(id2));
+        adjustment.kind = TransactionKind::Adjustment;
+        books.add_transaction(balanced).unwrap();
+        books.add_transaction(unbalanced).unwrap();
+        books.add_transaction(adjustment).unwrap();
+
+        let found = books.find_unbalanced();
+        assert_eq!(1, found.len());
+        assert_eq!(unbalanced_id, found[0].id);
+    }
+
+    #[test]
+    fn test_trial_balance_diagnostics() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction(Some(id1), Some(id2))).unwrap();
+        assert!(books.trial_balance_diagnostics().is_empty());
+
+        let mut unbalanced = build_transaction(Some(id1), Some(id2));
+        unbalanced.entries[0].amount = dec!(10050);
+        let unbalanced_id = unbalanced.id;
+        books.add_transaction(unbalanced).unwrap();
+        let single_sided = build_transaction(None, Some(id2));
+        let single_sided_id = single_sided.id;
+        books.add_transaction(single_sided).unwrap();
+        books.accounts.get_mut(&id1).unwrap().starting_balance = dec!(500);
+
+        let diagnostics = books.trial_balance_diagnostics();
+
+        assert_eq!(4, diagnostics.len());
+        assert_eq!(format!("Transaction {} on 2022-06-04 \"received moneys\" is unbalanced, debits exceed credits by 50", unbalanced_id), diagnostics[0]);
+        assert_eq!(format!("Transaction {} on 2022-06-04 \"received moneys\" has a single Credit entry of 10000", single_sided_id), diagnostics[1]);
+        assert_eq!("Starting balances are not offset to equity, debits exceed credits by 500", diagnostics[2]);
+        assert_eq!("Account Savings Account 1 has a starting balance of 500", diagnostics[3]);
+    }
+
+    #[test]
+    fn test_settle_foreign() {
+        let (mut books, bank_id, _) = setup_books();
+        let supplies = Account::create_new("Supplies", AccountType::Expense);
+        let supplies_id = supplies.id;
+        books.add_account(supplies).unwrap();
+        let payable = Account::create_new("Accounts Payable", AccountType::Liability);
+        let payable_id = payable.id;
+        books.add_account(payable).unwrap();
+        let fx = Account::create_new("FX Gain/Loss", AccountType::Expense);
+        let fx_id = fx.id;
+        books.add_account(fx).unwrap();
+
+        let mut bill = build_transaction_with_date(Some(supplies_id), Some(payable_id), NaiveDate::from_ymd(2022, 6, 4));
+        for e in bill.entries.iter_mut() {
+            e.amount = dec!(110.00);
+            e.currency = Some("EUR".to_string());
+            e.foreign_amount = Some(dec!(100));
+        }
+        let bill_id = bill.id;
+        books.add_transaction(bill).unwrap();
+
+        let settlement_id = books.settle_foreign(bill_id, bank_id, dec!(1.2), fx_id, NaiveDate::from_ymd(2022, 7, 4)).unwrap();
+        let settlement = books.transaction(settlement_id).unwrap();
+        assert!(settlement.is_balanced());
+        assert_eq!(3, settlement.entries.len());
+        assert_eq!(vec![bill_id], settlement.related);
+
+        let date = NaiveDate::from_ymd(2022, 7, 31);
+        assert_eq!(dec!(0), books.balance_as_of(payable_id, date).unwrap());
+        assert_eq!(dec!(-120.00), books.balance_as_of(bank_id, date).unwrap());
+        assert_eq!(dec!(10.00), books.balance_as_of(fx_id, date).unwrap());
+
+        let gain_id = books.settle_foreign(bill_id, bank_id, dec!(1.05), fx_id, NaiveDate::from_ymd(2022, 7, 5)).unwrap();
+        let gain = books.transaction(gain_id).unwrap();
+        assert!(gain.is_balanced());
+        assert_eq!(Side::Credit, gain.account_entries(fx_id)[0].entry_type);
+        assert_eq!(dec!(5.00), gain.account_entries(fx_id)[0].amount);
+        assert!(books.settle_foreign(Uuid::new_v4(), bank_id, dec!(1.2), fx_id, date).is_err());
+    }
+
+    #[test]
+    fn test_settle_foreign_rounds_to_currency() {
+        let (mut books, bank_id, _) = setup_books();
+        books.settings.default_currency = "JPY".to_string();
+        let supplies = Account::create_new("Supplies", AccountType::Expense);
+        let supplies_id = supplies.id;
+        books.add_account(supplies).unwrap();
+        let payable = Account::create_new("Accounts Payable", AccountType::Liability);
+        let payable_id = payable.id;
+        books.add_account(payable).unwrap();
+        let fx = Account::create_new("FX Gain/Loss", AccountType::Expense);
+        let fx_id = fx.id;
+        books.add_account(fx).unwrap();
+
+        let mut bill = build_transaction_with_date(Some(supplies_id), Some(payable_id), NaiveDate::from_ymd(2022, 6, 4));
+        for e in bill.entries.iter_mut() {
+            e.amount = dec!(13000);
+            e.currency = Some("USD".to_string());
+            e.foreign_amount = Some(dec!(100));
+        }
+        let bill_id = bill.id;
+        books.add_transaction(bill).unwrap();
+
+        let settlement_id = books.settle_foreign(bill_id, bank_id, dec!(133.337), fx_id, NaiveDate::from_ymd(2022, 7, 4)).unwrap();
+        let settlement = books.transaction(settlement_id).unwrap();
+        assert!(settlement.is_balanced());
+        assert_eq!(dec!(13334), settlement.account_entries(bank_id)[0].amount);
+        assert_eq!(dec!(334), settlement.account_entries(fx_id)[0].amount);
+
+        books.settings.default_currency = "BHD".to_string();
+        let settlement_id = books.settle_foreign(bill_id, bank_id, dec!(133.33375), fx_id, NaiveDate::from_ymd(2022, 7, 5)).unwrap();
+        assert_eq!(dec!(13333.375), books.transaction(settlement_id).unwrap().account_entries(bank_id)[0].amount);
+    }
+
+    #[test]
+    fn test_close_periods() {
+        let (mut books, bank_id, _) = setup_books();
+        let sales = Account::create_new("Sales", AccountType::Revenue);
+        let sales_id = sales.id;
+        books.add_account(sales).unwrap();
+        let rent = Account::create_new("Rent", AccountType::Expense);
+        let rent_id = rent.id;
+        books.add_account(rent).unwrap();
+        let retained = Account::create_new("Retained Earnings", AccountType::Equity);
+        let retained_id = retained.id;
+        books.add_account(retained).unwrap();
+
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(sales_id), NaiveDate::from_ymd(2022, 1, 10))).unwrap();
+        let mut t = build_transaction_with_date(Some(rent_id), Some(bank_id), NaiveDate::from_ymd(2022, 2, 1));
+        t.entries.iter_mut().for_each(|e| e.amount = dec!(2500));
+        books.add_transaction(t).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(sales_id), NaiveDate::from_ymd(2022, 2, 10))).unwrap();
+        let mut t = build_transaction_with_date(Some(rent_id), Some(bank_id), NaiveDate::from_ymd(2022, 3, 1));
+        t.entries.iter_mut().for_each(|e| e.amount = dec!(12500));
+        books.add_transaction(t).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(sales_id), NaiveDate::from_ymd(2022, 4, 10))).unwrap();
+        for date in [NaiveDate::from_ymd(2021, 12, 10), NaiveDate::from_ymd(2022, 2, 20)] {
+            let mut projected = build_transaction_with_date(Some(bank_id), Some(sales_id), date);
+            projected.status = TransactionStatus::Projected;
+            books.add_transaction(projected).unwrap();
+        }
+
+        let closing_ids = books.close_periods(NaiveDate::from_ymd(2022, 3, 31), retained_id, ScheduleEnum::Months).unwrap();
+        assert_eq!(3, closing_ids.len());
+        let closings: Vec<Transaction> = closing_ids.iter().map(|id| books.transaction(*id).unwrap()).collect();
+        assert_eq!(NaiveDate::from_ymd(2022, 1, 31), closings[0].entries[0].date);
+        assert_eq!(NaiveDate::from_ymd(2022, 3, 31), closings[2].entries[0].date);
+        assert!(closings.iter().all(|t| t.is_balanced() && t.kind == TransactionKind::Closing));
+
+        let retained_net: Vec<Decimal> = closings.iter()
+            .map(|t| t.account_entries(retained_id)[0].signed_amount(Side::Credit))
+            .collect();
+        assert_eq!(vec![dec!(10000), dec!(7500), dec!(-12500)], retained_net);
+
+        let march_end = NaiveDate::from_ymd(2022, 3, 31);
+        // Only the projected sales are left open.
+        assert_eq!(dec!(20000), books.balance_as_of(sales_id, march_end).unwrap());
+        assert_eq!(dec!(0), books.balance_as_of(rent_id, march_end).unwrap());
+        assert_eq!(dec!(5000), books.balance_as_of(retained_id, march_end).unwrap());
+        assert!(books.close_periods(march_end, retained_id, ScheduleEnum::Months).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_close_quarters() {
+        let (mut books, bank_id, _) = setup_books();
+        let sales = Account::create_new("Sales", AccountType::Revenue);
+        let sales_id = sales.id;
+        books.add_account(sales).unwrap();
+        let retained = Account::create_new("Retained Earnings", AccountType::Equity);
+        let retained_id = retained.id;
+        books.add_account(retained).unwrap();
+
+        for date in [NaiveDate::from_ymd(2022, 2, 10), NaiveDate::from_ymd(2022, 3, 31), NaiveDate::from_ymd(2022, 8, 1), NaiveDate::from_ymd(2022, 10, 1)] {
+            books.add_transaction(build_transaction_with_date(Some(bank_id), Some(sales_id), date)).unwrap();
+        }
+
+        let closing_ids = books.close_periods(NaiveDate::from_ymd(2022, 11, 30), retained_id, ScheduleEnum::Quarters).unwrap();
+        assert_eq!(2, closing_ids.len());
+        let closings: Vec<Transaction> = closing_ids.iter().map(|id| books.transaction(*id).unwrap()).collect();
+        assert_eq!(NaiveDate::from_ymd(2022, 3, 31), closings[0].entries[0].date);
+        assert_eq!(NaiveDate::from_ymd(2022, 9, 30), closings[1].entries[0].date);
+        assert_eq!(dec!(20000), closings[0].account_entries(retained_id)[0].signed_amount(Side::Credit));
+        assert_eq!(dec!(10000), closings[1].account_entries(retained_id)[0].signed_amount(Side::Credit));
+        assert_eq!(dec!(10000), books.balance_as_of(sales_id, NaiveDate::from_ymd(2022, 11, 30)).unwrap());
+        assert!(books.close_periods(NaiveDate::from_ymd(2022, 12, 31), retained_id, ScheduleEnum::Weeks).is_err());
+    }
+
+    #[test]
+    fn test_single_sided_transactions() {
+        let (mut books, id1, id2) = setup_books();
+        let single = build_transaction(Some(id1), None);
+        let single_id = single.id;
+        books.add_transaction(build_transaction(Some(id1), Some(id2))).unwrap();
+        books.add_transaction(single).unwrap();
+
+        let found = books.single_sided_transactions();
+        assert_eq!(1, found.len());
+        assert_eq!(single_id, found[0].id);
+    }
+
+    #[test]
+    fn test_validate_account_dates() {
+        let (mut books, id1, id2) = setup_books();
+        let transaction = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let entry_id = transaction.entries[0].id;
+        books.add_transaction(transaction).unwrap();
+        assert!(books.validate_account_dates().is_empty());
+
+        books.accounts.get_mut(&id1).unwrap().opened = Some(NaiveDate::from_ymd(2022, 7, 1));
+        books.accounts.get_mut(&id2).unwrap().opened = Some(NaiveDate::from_ymd(2022, 6, 4));
+
+        assert_eq!(vec![(entry_id, NaiveDate::from_ymd(2022, 6, 4))], books.validate_account_dates());
+    }
+
+    #[test]
+    fn test_reconcile_entry() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let (t1_id, entry_id) = (t1.id, t1.entries[0].id);
+        books.add_transaction(t1).unwrap();
+        let t2 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 5));
+        let t2_entry_id = t2.entries[0].id;
+        books.add_transaction(t2).unwrap();
+        let mut projected = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 6));
+        projected.status = TransactionStatus::Projected;
+        books.add_transaction(projected).unwrap();
+        assert_eq!(2, books.unreconciled_entries(id1).len());
+
+        books.reconcile_entry(t1_id, entry_id).unwrap();
+        let outstanding = books.unreconciled_entries(id1);
+        assert_eq!(1, outstanding.len());
+        assert_eq!(t2_entry_id, outstanding[0].id);
+        assert_eq!(2, books.unreconciled_entries(id2).len());
+
+        books.unreconcile_entry(t1_id, entry_id).unwrap();
+        assert_eq!(2, books.unreconciled_entries(id1).len());
+        assert!(books.reconcile_entry(Uuid::new_v4(), entry_id).is_err());
+        assert_eq!(format!("Entry {} not found in transaction {}.", t2_entry_id, t1_id), books.reconcile_entry(t1_id, t2_entry_id).err().unwrap().error);
+    }
+
+    #[test]
+    fn test_approve_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.require_approval = true;
+        let mut t = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        t.approved = false;
+        let transaction_id = t.id;
+        books.add_transaction(t).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+
+        assert_eq!(dec!(0), books.balance_as_of(id1, date).unwrap());
+        assert_eq!(dec!(0), books.balances_as_of(date)[&id2]);
+        assert_eq!(None, books.account_entries(id1).unwrap()[0].balance);
+
+        books.approve_transaction(transaction_id).unwrap();
+        assert_eq!(dec!(10000), books.balance_as_of(id1, date).unwrap());
+        assert_eq!(dec!(-10000), books.balances_as_of(date)[&id2]);
+        assert_eq!(Some(dec!(10000)), books.account_entries(id1).unwrap()[0].balance);
+
+        assert!(books.approve_transaction(Uuid::new_v4()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        let t = build_transaction(Some(id1), Some(id2));
+        let original_id = t.id;
+        books.add_transaction(t).unwrap();
+        let new_date = NaiveDate::from_ymd(2022, 7, 1);
+
+        let copy_id = books.duplicate_transaction(original_id, new_date).unwrap();
+
+        assert_ne!(original_id, copy_id);
+        assert_eq!(2, books.transactions().len());
+        let original = books.transaction(original_id).unwrap();
+        let copy = books.transaction(copy_id).unwrap();
+        assert_eq!(Some(new_date), copy.date());
+        assert_eq!(Some(NaiveDate::from_ymd(2022, 6, 4)), original.date());
+        for (o, c) in original.entries.iter().zip(copy.entries.iter()) {
+            assert_ne!(o.id, c.id);
+            assert_eq!(copy_id, c.transaction_id);
+            assert_eq!(o.account_id, c.account_id);
+            assert_eq!(o.amount, c.amount);
+            assert_eq!(o.entry_type, c.entry_type);
+            assert_eq!(new_date, c.date);
+        }
+        assert!(books.duplicate_transaction(Uuid::new_v4(), new_date).is_err());
+    }
+
+    #[test]
+    fn test_delete_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(Some(id1), Some(id2));
+        let t1_id = t1.id;
+        books.add_transaction(t1).unwrap();
+
+        let _result = books.delete_transaction(&t1_id);
+        let expected: Result<(), BooksError> = Ok(());
+        assert!(matches!(expected, _result));
+        assert_eq!(0, books.transactions.len());
+    }
+
+    #[test]
+    fn test_delete_invalid_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction(Some(id1), Some(id2));
+        books.add_transaction(t1).unwrap();
+
+        let id = &Uuid::new_v4();
+        let result = books.delete_transaction(&id);
+        assert_eq!(format!("Transaction {} not found.", id  ).as_str(), result.err().unwrap().error);
+        assert_eq!(1, books.transactions.len());
+    }
+
+
+    #[test]
+    fn test_account_entries_same_day_order() {
+        let (mut books, id1, id2) = setup_books();
+        let date = NaiveDate::from_ymd(2022, 6, 4);
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 5))).unwrap();
+        let mut ids = Vec::new();
+        for amount in [dec!(300), dec!(100), dec!(200)] {
+            let mut t = build_transaction_with_date(Some(id1), Some(id2), date);
+            t.entries.iter_mut().for_each(|e| e.amount = amount);
+            ids.push(t.id);
+            books.add_transaction(t).unwrap();
+        }
+
+        let first = books.account_entries(id1).unwrap();
+        assert_eq!(ids, first[..3].iter().map(|e| e.transaction_id).collect::<Vec<Uuid>>());
+        assert_eq!(vec![Some(dec!(300)), Some(dec!(400)), Some(dec!(600)), Some(dec!(10600))], first.iter().map(|e| e.balance).collect::<Vec<Option<Decimal>>>());
+        for _ in 0..5 {
+            let again = books.account_entries(id1).unwrap();
+            assert_eq!(first.iter().map(|e| e.id).collect::<Vec<Uuid>>(), again.iter().map(|e| e.id).collect::<Vec<Uuid>>());
+        }
+    }
+
+    #[test]
+    fn test_account_entries() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let t2 = build_transaction_with_date(None, Some(id2),NaiveDate::from_ymd(2022, 6, 5));
+        let t3 = build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 7, 1));
+        let t4 = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 7, 2));
+        let t1a1e1 = &t1.account_entries(id1)[0];
+        let t3a1e3 = &t3.account_entries(id1)[0];
+        let t4a1e4 = &t4.account_entries(id1)[0];
+        let t1a2e1 = &t1.account_entries(id2)[0];
+        let t2a2e1 = &t2.account_entries(id2)[0];
+        let t4a2e1 = &t4.account_entries(id2)[0];
+        books.add_transaction(t1).unwrap();
+        books.add_transaction(t2).unwrap();
+        books.add_transaction(t3).unwrap();
+        books.add_transaction(t4).unwrap();
+        let a1_entries = books.account_entries(id1).unwrap();
+        assert_eq!(3, a1_entries.len());
+
+        let entry1 = &a1_entries[0];
+        assert_eq!(t1a1e1.id, entry1.id);
+        assert_eq!(dec!(10000), entry1.balance.unwrap());
+
+        let entry2 = &a1_entries[1];
+        assert_eq!(t3a1e3.id, entry2.id);
+        assert_eq!(dec!(20000), entry2.balance.unwrap());
+
+        let entry3 = &a1_entries[2];
+        println!("{:?}", entry3);
+        assert_eq!(t4a1e4.id, entry3.id);
+        assert_eq!(dec!(10000), entry3.balance.unwrap());
+
+        let a2_entries = books.account_entries(id2).unwrap();
+        assert_eq!(3, a2_entries.len());
+
+        let entry21 = &a2_entries[0];
+        assert_eq!(t1a2e1.id, entry21.id);
+        assert_eq!(dec!(-10000), entry21.balance.unwrap());
+
+        let entry22 = &a2_entries[1];
+        assert_eq!(t2a2e1.id, entry22.id);
+        assert_eq!(dec!(-20000), entry22.balance.unwrap());
+
+        let entry23 = &a2_entries[2];
+        assert_eq!(t4a2e1.id, entry23.id);
+        assert_eq!(dec!(-10000), entry23.balance.unwrap());
+
+
+    }
+
+    #[test]
+    fn test_account_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let t1a1e1 = &t1.account_entries(id1)[0];
+        let _t1a2e1 = &t1.account_entries(id2)[0];
+        books.add_transaction(t1).unwrap();
+        let a1_entries = books.account_transactions(id1).unwrap();
+        assert_eq!(1, a1_entries.len());
+
+        let entry1 = &a1_entries[0].account_entries(id1)[0];
+        assert_eq!(t1a1e1.id, entry1.id);
+        assert_eq!(dec!(10000), entry1.balance.unwrap());
+    }
+
+
+
+    #[test]
+    fn test_account_transactions() {
+        let (mut books, id1, id2) = setup_books();
+        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let t2 = build_transaction_with_date(None, Some(id2),NaiveDate::from_ymd(2022, 6, 5));
+        let t3 = build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 7, 1));
+        let t4 = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 7, 2));
+        let t1a1e1 = &t1.account_entries(id1)[0];
+        let t3a1e3 = &t3.account_entries(id1)[0];
+        let t4a1e4 = &t4.account_entries(id1)[0];
+        let t1a2e1 = &t1.account_entries(id2)[0];
+        let t2a2e1 = &t2.account_entries(id2)[0];
+        let t4a2e1 = &t4.account_entries(id2)[0];
+        books.add_transaction(t1).unwrap();
+        books.add_transaction(t2).unwrap();
+        books.add_transaction(t3).unwrap();
+        books.add_transaction(t4).unwrap();
+        let a1_entries = books.account_transactions(id1).unwrap();
+        assert_eq!(3, a1_entries.len());
+
+        let entry1 = &a1_entries[0].account_entries(id1)[0];
+        assert_eq!(t1a1e1.id, entry1.id);
+        assert_eq!(dec!(10000), entry1.balance.unwrap());
+
+        let entry2 = &a1_entries[1].account_entries(id1)[0];
+        assert_eq!(t3a1e3.id, entry2.id);
+        assert_eq!(dec!(20000), entry2.balance.unwrap());
+
+        let entry3 = &a1_entries[2].account_entries(id1)[0];
+        println!("{:?}", entry3);
+        assert_eq!(t4a1e4.id, entry3.id);
+        assert_eq!(dec!(10000), entry3.balance.unwrap());
+
+        let a2_entries = books.account_transactions(id2).unwrap();
+        assert_eq!(3, a2_entries.len());
+
+        let entry21 = &a2_entries[0].account_entries(id2)[0];
+        assert_eq!(t1a2e1.id, entry21.id);
+        assert_eq!(dec!(-10000), entry21.balance.unwrap());
+
+        let entry22 = &a2_entries[1].account_entries(id2)[0];
+        assert_eq!(t2a2e1.id, entry22.id);
+        assert_eq!(dec!(-20000), entry22.balance.unwrap());
+
+        let entry23 = &a2_entries[2].account_entries(id2)[0];
+        assert_eq!(t4a2e1.id, entry23.id);
+        assert_eq!(dec!(-10000), entry23.balance.unwrap());
+
 
-        None
     }
 
-    pub fn update_schedule(&mut self, schedule: Schedule) -> Result<(), BooksError> {
-        if let Some(value) = self.validate_schedule(&schedule) {
-            return value;
-        }
+    #[test]
+    fn test_recompute_entry_balances() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 7, 1))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 5))).unwrap();
+        books.transactions[1].entries[0].balance = Some(dec!(99));
+        assert!(books.transactions[0].entries[0].balance.is_none());
 
-        self.scheduler.update_schedule(schedule)
+        books.recompute_entry_balances();
+        let expected = books.account_entries(id1).unwrap();
+        let actual: Vec<(Uuid, Option<Decimal>)> = books.transactions.iter()
+            .flat_map(|t| t.account_entries(id1))
+            .map(|e| (e.id, e.balance))
+            .collect();
+        assert_eq!(expected.iter().map(|e| (e.id, e.balance)).collect::<Vec<(Uuid, Option<Decimal>)>>(), actual);
+        assert_eq!(vec![Some(dec!(10000)), Some(dec!(0)), Some(dec!(10000))], actual.iter().map(|(_, b)| *b).collect::<Vec<Option<Decimal>>>());
+        assert_eq!(Some(dec!(0)), books.transactions[1].account_entries(id2)[0].balance);
     }
 
-    pub fn schedules(&self) -> &[Schedule] {
-        self.scheduler.schedules()
+    #[test]
+    fn test_account_transactions_with_empty_transaction() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 5))).unwrap();
+        let mut empty = build_transaction_with_date(None, None, NaiveDate::from_ymd(2022, 6, 4));
+        empty.entries.clear();
+        books.transactions.push(empty);
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 3))).unwrap();
+
+        let transactions = books.account_transactions(id1).unwrap();
+        assert_eq!(2, transactions.len());
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 3), transactions[0].entries[0].date);
+        assert_eq!(dec!(-10000), transactions[0].account_entries(id1)[0].balance.unwrap());
+        assert_eq!(dec!(0), transactions[1].account_entries(id1)[0].balance.unwrap());
+
+        let entries = books.account_entries(id1).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(dec!(0), entries[1].balance.unwrap());
     }
 
-    pub fn end_date(&self) -> Option<NaiveDate> {
-        self.scheduler.end_date()
+    #[test]
+    fn test_add_schedule() {
+        let (mut books, id1, id2) = setup_books();
+        let st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        let _result = books.add_schedule(st1);
+        let expected: Result<(), BooksError> = Err(BooksError { error: "Invalid CR account".to_string() });
+        assert!(matches!(expected, _result));
+        assert_eq!(1, (&books.schedules()).len());
     }
 
-    fn valid_account_id(&self, id: Option<Uuid>) -> bool {
-        match id {
-            Some(k) => return self.accounts.contains_key(&k),
-            None => return true
-        }
+    #[test]
+    fn test_schedule_modifier_validation() {
+        let (mut books, id1, id2) = setup_books();
+        let mut st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        st1.end_date = Some(NaiveDate::from_ymd(2023, 6, 4));
+        st1.modifier = Some(Modifier {
+            kind: ModifierKind::PercentOfAccountBalance { account_id: id1, percentage: dec!(1) },
+            start_date: NaiveDate::from_ymd(2022, 7, 4),
+            period: ScheduleEnum::Months,
+            frequency: 1,
+        });
+        let mut st2 = st1.clone();
+        st2.id = Uuid::new_v4();
+        let mut st3 = st1.clone();
+        st3.id = Uuid::new_v4();
+        books.add_schedule(st1).unwrap();
+
+        st2.modifier.as_mut().unwrap().frequency = -1;
+        assert_eq!("Modifier frequency must be at least 1, got -1", books.add_schedule(st2).err().unwrap().error);
+        st3.modifier.as_mut().unwrap().start_date = NaiveDate::from_ymd(2023, 7, 4);
+        assert_eq!("Modifier start date 2023-07-04 is after the schedule end date 2023-06-04", books.add_schedule(st3).err().unwrap().error);
+        assert_eq!(1, books.schedules().len());
     }
-}
 
-#[derive(Debug)]
-pub struct BooksError {
-    pub error: String,
-}
+    #[test]
+    fn test_update_schedule() {
+        let (mut books, id1, id2) = setup_books();
+        let st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        let mut st1_copy = st1.clone();
+        let _result = books.add_schedule(st1);
 
-impl BooksError {
-    pub fn from_str(name: &str) -> BooksError {
-        BooksError { error: String::from(name) }
+        st1_copy.entries[0].description = "test changed".to_string();
+        let _result = books.update_schedule(st1_copy);
+        assert_eq!(1, (books.schedules()).len());
+        assert_eq!("test changed", books.schedules()[0].entries[0].description);
     }
-}
 
-#[cfg(test)]
+    #[test]
+    fn test_delete_schedule() {
+        let (mut books, id1, id2) = setup_books();
+        let st1 = build_schedule(id1, id2, NaiveDate::from_ymd(2022, 3, 11), "S_1", "st test 1", dec!(100.99), 3, ScheduleEnum::Months);
+        let st2 = build_schedule(id2, id1, NaiveDate::from_ymd(2022, 3, 11), "S_2", "st test 2", dec!(20.23), 3, ScheduleEnum::Months);
+        let (st1_id, st2_id) = (st1.id, st2.id);
+        books.add_schedule(st1).unwrap();
+        books.add_schedule(st2).unwrap();
+        books.generate(NaiveDate::from_ymd(2022, 12, 31), today()).unwrap();
+        assert_eq!(8, books.transactions.len());
+        books.transactions.iter_mut()
+            .filter(|t| t.schedule_id() == Some(st1_id))
+            .take(1)
+            .for_each(|t| t.status = TransactionStatus::Recorded);
 
-mod tests {
-    use rust_decimal::Decimal;
-    use uuid::Uuid;
-    use chrono::{NaiveDate};
-    use rust_decimal_macros::dec;
-    use crate::{account::*, books::BooksError};
+        books.delete_schedule(&st2_id, false).unwrap();
+        assert_eq!(1, books.schedules().len());
+        assert_eq!(8, books.transactions.len());
 
-    use super::Books;
+        books.delete_schedule(&st1_id, true).unwrap();
+        assert_eq!(0, books.schedules().len());
+        assert_eq!(5, books.transactions.len());
+        assert_eq!(1, books.transactions.iter().filter(|t| t.schedule_id() == Some(st1_id)).count());
+
+        assert_eq!(format!("Schedule {} not found.", st1_id), books.delete_schedule(&st1_id, false).err().unwrap().error);
+    }
 
     #[test]
-    fn test_add_account(){
-        let a = Account::create_new("test account", AccountType::Liability);
-        let id1 = a.id;
-        let mut b = Books::build_empty("My Books");
-        b.add_account(a);
+    fn test_schedules_affecting() {
+        let (mut books, id1, id2) = setup_books();
+        let other = Account::create_new("Other", AccountType::Asset);
+        let other_id = other.id;
+        books.add_account(other).unwrap();
+        let touching = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        let touching_id = touching.id;
+        books.add_schedule(touching).unwrap();
+        books.add_schedule(build_schedule_std(other_id, id2, NaiveDate::from_ymd(2022, 6, 4))).unwrap();
 
-        let a2 = &b.accounts()[0];
-        assert_eq!(id1, a2.id);
+        assert_eq!(vec![touching_id], books.schedules_affecting(id1).iter().map(|s| s.id).collect::<Vec<Uuid>>());
+        assert_eq!(2, books.schedules_affecting(id2).len());
     }
 
     #[test]
-    fn test_delete_account(){
+    fn test_days_until_next() {
         let (mut books, id1, id2) = setup_books();
-        let _result = books.delete_account(&id1);
-        assert!(matches!((), _result));
-        assert!(books.accounts.get(&id1).is_none());
-        assert!(books.accounts.get(&id2).is_some());
+        let mut schedule = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        let schedule_id = schedule.id;
+        schedule.end_date = Some(NaiveDate::from_ymd(2022, 7, 4));
+        books.add_schedule(schedule).unwrap();
+
+        assert_eq!(Some(3), books.days_until_next(schedule_id, NaiveDate::from_ymd(2022, 6, 1)));
+        assert_eq!(Some(-5), books.days_until_next(schedule_id, NaiveDate::from_ymd(2022, 6, 9)));
+
+        books.generate(NaiveDate::from_ymd(2022, 12, 31), today()).unwrap();
+        assert_eq!(None, books.days_until_next(schedule_id, NaiveDate::from_ymd(2022, 6, 1)));
+        assert_eq!(None, books.days_until_next(Uuid::new_v4(), NaiveDate::from_ymd(2022, 6, 1)));
     }
 
     #[test]
-    fn test_cannot_delete_account_with_transactions(){
+    fn test_future_dated_transactions() {
         let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction(None, Some(id1));
-        books.add_transaction(t1).unwrap();
-        let result = books.delete_account(&id1);
-        assert_eq!(format!("Account {} can not be deleted as it has transactions.", id1).as_str(), result.err().unwrap().error);
-        assert!(books.accounts.get(&id1).is_some());
-        assert!(books.accounts.get(&id2).is_some());
+        let today = NaiveDate::from_ymd(2022, 6, 15);
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        let future = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 7, 1));
+        let future_id = future.id;
+        books.add_transaction(future).unwrap();
+        let mut projected = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 8, 1));
+        projected.status = TransactionStatus::Projected;
+        books.add_transaction(projected).unwrap();
+
+        let found = books.future_dated_transactions(today);
+        assert_eq!(vec![future_id], found.iter().map(|t| t.id).collect::<Vec<Uuid>>());
+        assert!(books.future_dated_transactions(NaiveDate::from_ymd(2022, 7, 1)).is_empty());
     }
 
     #[test]
-    fn test_cannot_delete_with_invalid_account_id(){
+    fn test_import_transactions_json() {
         let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction(None, Some(id1));
-        books.add_transaction(t1).unwrap();
-        let id = &Uuid::new_v4();
-        let result = books.delete_account(id);
-        assert_eq!(format!("Account {} not found.", id  ).as_str(), result.err().unwrap().error);
-        assert!(books.accounts.get(&id1).is_some());
-        assert!(books.accounts.get(&id2).is_some());
+        let transactions = vec![
+            build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4)),
+            build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 1)),
+        ];
+        let json = serde_json::to_string(&transactions).unwrap();
+
+        assert_eq!(2, books.import_transactions_json(&json).unwrap());
+        assert_eq!(transactions[1].id, books.transactions()[0].id);
+
+        let missing = Uuid::new_v4();
+        let invalid = vec![
+            build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 5)),
+            build_transaction_with_date(Some(id1), Some(missing), NaiveDate::from_ymd(2022, 6, 6)),
+        ];
+        let error = books.import_transactions_json(&serde_json::to_string(&invalid).unwrap()).err().unwrap().error;
+        assert_eq!(format!("Transaction 2 ({}) is invalid: Account not found for id: {}", invalid[1].id, missing), error);
+        assert_eq!(2, books.transactions().len());
+        assert!(books.import_transactions_json("{}").is_err());
+
+        let new = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 7));
+        let repeated = vec![new.clone(), new.clone()];
+        let error = books.import_transactions_json(&serde_json::to_string(&repeated).unwrap()).err().unwrap().error;
+        assert_eq!(format!("Transaction 2 ({}) is a duplicate.", new.id), error);
+        let existing = vec![new, transactions[0].clone()];
+        let error = books.import_transactions_json(&serde_json::to_string(&existing).unwrap()).err().unwrap().error;
+        assert_eq!(format!("Transaction 2 ({}) is a duplicate.", transactions[0].id), error);
+        assert_eq!(2, books.transactions().len());
     }
 
     #[test]
-    fn test_add_transaction() {
+    fn test_search_transactions() {
         let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction(Some(id1), Some(id2));
-        let t1_id = t1.id;
-        books.add_transaction(t1).unwrap();
-        let t1_2 = &books.transactions()[0];
-        assert_eq!(t1_id, t1_2.id);
+        let mut coffee = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 1));
+        coffee.entries[1].description = "Morning COFFEE".to_string();
+        let coffee_id = coffee.id;
+        books.add_transaction(coffee).unwrap();
+        let mut rent = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 2));
+        rent.entries.iter_mut().for_each(|e| e.amount = dec!(1250.50));
+        let rent_id = rent.id;
+        books.add_transaction(rent).unwrap();
+
+        let ids = |found: Vec<Transaction>| found.iter().map(|t| t.id).collect::<Vec<Uuid>>();
+        assert_eq!(vec![coffee_id], ids(books.search_transactions("coffee")));
+        assert_eq!(vec![rent_id], ids(books.search_transactions("1250.5")));
+        assert_eq!(2, books.search_transactions("").len());
+        assert!(books.search_transactions("tea").is_empty());
     }
 
     #[test]
-    fn test_double_entry_required() {
+    fn test_export_import_schedule() {
         let (mut books, id1, id2) = setup_books();
-        books.settings.require_double_entry = true;
-        assert_eq!(0, books.transactions.len());
-        let mut t1 = build_transaction(Some(id1), Some(id2));
-        t1.entries.pop();
-        let result = books.add_transaction(t1);
-        assert_eq!("A transaction needs at least two entries (double entry required is on).".to_string(), result.err().unwrap().error);
-        assert_eq!(0, books.transactions.len());
+        let mut schedule = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 15));
+        schedule.last_date = Some(NaiveDate::from_ymd(2022, 3, 15));
+        let schedule_id = schedule.id;
+        books.add_schedule(schedule).unwrap();
+        let json = books.export_schedule(schedule_id).unwrap();
+        assert!(books.export_schedule(Uuid::new_v4()).is_err());
+
+        let (mut other, other_id1, other_id2) = setup_books();
+        let mapping = HashMap::from([(id1, other_id1), (id2, other_id2)]);
+        let imported_id = other.import_schedule(&json, mapping).unwrap();
+
+        assert_ne!(schedule_id, imported_id);
+        let imported = &other.schedules()[0];
+        assert_eq!(imported_id, imported.id);
+        assert_eq!("Reocurring transaction", imported.name);
+        assert_eq!(None, imported.last_date);
+        assert_eq!(vec![other_id1, other_id2], imported.entries.iter().map(|e| e.account_id).collect::<Vec<Uuid>>());
+        assert!(imported.entries.iter().all(|e| e.schedule_id == imported_id));
+        assert!(other.import_schedule(&json, HashMap::new()).is_err());
     }
 
     #[test]
-    fn test_at_least_one_entry_required() {
+    fn test_patch_schedule() {
         let (mut books, id1, id2) = setup_books();
-        assert_eq!(0, books.transactions.len());
-        let mut t1 = build_transaction(Some(id1), Some(id2));
-        t1.entries.pop();
-        t1.entries.pop();
-        let result = books.add_transaction(t1);
-        assert_eq!("A transaction must have at least one entry".to_string(), result.err().unwrap().error);
-        assert_eq!(0, books.transactions.len());
+        let mut schedule = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 15));
+        schedule.end_date = Some(NaiveDate::from_ymd(2022, 6, 15));
+        let schedule_id = schedule.id;
+        books.add_schedule(schedule).unwrap();
+
+        books.patch_schedule(schedule_id, Some("Renamed".to_string()), None, Some(None)).unwrap();
+
+        assert_eq!("Renamed", books.schedules()[0].name);
+        assert_eq!(None, books.schedules()[0].end_date);
+        assert!(books.schedules()[0].active);
+        assert_eq!(2, books.schedules()[0].entries.len());
+
+        books.patch_schedule(schedule_id, None, Some(false), None).unwrap();
+        assert_eq!("Renamed", books.schedules()[0].name);
+        assert!(!books.schedules()[0].active);
+        books.generate(NaiveDate::from_ymd(2022, 12, 31), today()).unwrap();
+        assert_eq!(0, books.transactions().len());
+
+        assert!(books.patch_schedule(Uuid::new_v4(), None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_add_schedule_invalid_dr_account() {
+        let (mut books, id1, id2) = setup_books();
+        let st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        let _result = books.add_schedule(st1);
+        let expected: Result<(), BooksError> = Ok(());
+        assert!(matches!(expected, _result));
+        assert_eq!(1, (&books.schedules()).len());
+    }
+
+    #[test]
+    fn test_add_schedule_invalid_cr_account() {
+        let (mut books, id1, _) = setup_books();
+        let st1 = build_schedule_std(id1, Uuid::new_v4(), NaiveDate::from_ymd(2022, 6, 4));
+        let _result = books.add_schedule(st1);
+        let expected: Result<(), BooksError> = Err(BooksError { error: "Invalid CR account".to_string() });
+        assert!(matches!(expected, _result));
+        assert_eq!(0, (&books.schedules()).len());
+    }
+
+    #[test]
+    fn test_group_balance() {
+        let (mut books, id1, id2) = setup_books();
+        let card = Account::create_new("Credit Card", AccountType::Liability);
+        let id3 = card.id;
+        books.add_account(card).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), None, NaiveDate::from_ymd(2022, 6, 5))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id3), NaiveDate::from_ymd(2022, 6, 6))).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+
+        assert_eq!(dec!(30000), books.group_balance(&[id1, id2], date).unwrap());
+        assert_eq!(dec!(20000), books.group_balance(&[id1, id2, id3], date).unwrap());
+        assert!(books.group_balance(&[id1, Uuid::new_v4()], date).is_err());
+    }
+
+    #[test]
+    fn test_computed_equity() {
+        let (mut books, id1, id2) = setup_books();
+        let equity = Account::create_new("Owner's Equity", AccountType::Equity);
+        let equity_id = equity.id;
+        books.add_account(equity).unwrap();
+        let loan = Account::create_new("Loan", AccountType::Liability);
+        let loan_id = loan.id;
+        books.add_account(loan).unwrap();
+        let income = Account::create_new("Interest", AccountType::Revenue);
+        let income_id = income.id;
+        books.add_account(income).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(equity_id), NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(loan_id), NaiveDate::from_ymd(2022, 6, 5))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(income_id), NaiveDate::from_ymd(2022, 6, 6))).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+
+        assert_eq!(dec!(20000), books.computed_equity(date));
+        assert!(books.equity_matches_accounts(date));
+
+        books.add_transaction(build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 6, 7))).unwrap();
+        assert_eq!(dec!(30000), books.computed_equity(date));
+        assert!(!books.equity_matches_accounts(date));
+    }
+
+    #[test]
+    fn test_equity_statement() {
+        let (mut books, id1, _) = setup_books();
+        let mut equity = Account::create_new("Owner's Equity", AccountType::Equity);
+        equity.starting_balance = dec!(500);
+        let equity_id = equity.id;
+        books.add_account(equity).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(equity_id), NaiveDate::from_ymd(2022, 5, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(equity_id), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(equity_id), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
+
+        let statement = books.equity_statement(NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30));
+        assert_eq!(1, statement.len());
+        let (account, opening, change, closing) = &statement[0];
+        assert_eq!(equity_id, account.id);
+        assert_eq!(dec!(10500), *opening);
+        assert_eq!(dec!(10000), *change);
+        assert_eq!(dec!(20500), *closing);
+    }
+
+    #[test]
+    fn test_monthly_net() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 1, 10))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 1, 20))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 3, 5))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 4, 1))).unwrap();
+
+        let net = books.monthly_net(id1, NaiveDate::from_ymd(2022, 1, 1), NaiveDate::from_ymd(2022, 3, 31));
+
+        assert_eq!(vec![(2022, 1, dec!(20000)), (2022, 2, dec!(0)), (2022, 3, dec!(-10000))], net);
+    }
+
+    #[test]
+    fn test_month_end_balances() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2024, 1, 31))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2024, 3, 1))).unwrap();
+
+        let balances = books.month_end_balances(id1, NaiveDate::from_ymd(2024, 1, 15), NaiveDate::from_ymd(2024, 4, 29));
+
+        assert_eq!(vec![
+            (NaiveDate::from_ymd(2024, 1, 31), dec!(10000)),
+            (NaiveDate::from_ymd(2024, 2, 29), dec!(10000)),
+            (NaiveDate::from_ymd(2024, 3, 31), dec!(20000)),
+        ], balances);
+        assert_eq!(NaiveDate::from_ymd(2023, 2, 28), books.month_end_balances(id1, NaiveDate::from_ymd(2023, 2, 1), NaiveDate::from_ymd(2023, 2, 28))[0].0);
+        assert!(books.month_end_balances(Uuid::new_v4(), NaiveDate::from_ymd(2024, 1, 1), NaiveDate::from_ymd(2024, 3, 31)).is_empty());
+    }
+
+    #[test]
+    fn test_average_monthly_spend() {
+        let (mut books, bank_id, _) = setup_books();
+        let groceries = Account::create_new("Groceries", AccountType::Expense);
+        let groceries_id = groceries.id;
+        books.add_account(groceries).unwrap();
+        for (month, amount) in [(1, dec!(50)), (2, dec!(100)), (3, dec!(200)), (4, dec!(300)), (5, dec!(1000))] {
+            let mut t = build_transaction_with_date(Some(groceries_id), Some(bank_id), NaiveDate::from_ymd(2022, month, 10));
+            t.entries.iter_mut().for_each(|e| e.amount = amount);
+            books.add_transaction(t).unwrap();
+        }
+
+        assert_eq!(dec!(200), books.average_monthly_spend(groceries_id, 3, NaiveDate::from_ymd(2022, 4, 30)).unwrap());
+        assert_eq!(dec!(150), books.average_monthly_spend(groceries_id, 2, NaiveDate::from_ymd(2022, 5, 9)).unwrap());
+        assert!(books.average_monthly_spend(groceries_id, 0, NaiveDate::from_ymd(2022, 4, 30)).is_err());
+        assert!(books.average_monthly_spend(Uuid::new_v4(), 3, NaiveDate::from_ymd(2022, 4, 30)).is_err());
+    }
+
+    #[test]
+    fn test_budget_utilization() {
+        let (mut books, bank_id, _) = setup_books();
+        let mut groceries = Account::create_new("Groceries", AccountType::Expense);
+        let groceries_id = groceries.id;
+        groceries.monthly_budget = Some(dec!(500));
+        books.add_account(groceries).unwrap();
+        for (day, amount) in [(3, dec!(100)), (17, dec!(150))] {
+            let mut t = build_transaction_with_date(Some(groceries_id), Some(bank_id), NaiveDate::from_ymd(2022, 6, day));
+            t.entries.iter_mut().for_each(|e| e.amount = amount);
+            books.add_transaction(t).unwrap();
+        }
+        books.add_transaction(build_transaction_with_date(Some(groceries_id), Some(bank_id), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
+
+        assert_eq!(dec!(50), books.budget_utilization(groceries_id, NaiveDate::from_ymd(2022, 6, 15)).unwrap());
+        assert_eq!("Account Savings Account 1 has no budget", books.budget_utilization(bank_id, NaiveDate::from_ymd(2022, 6, 15)).err().unwrap().error);
+        books.accounts.get_mut(&groceries_id).unwrap().monthly_budget = Some(Decimal::ZERO);
+        assert!(books.budget_utilization(groceries_id, NaiveDate::from_ymd(2022, 6, 15)).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "integer_amounts"))]
+    fn test_account_statement_json() {
+        let (mut books, id1, id2) = setup_books();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 5, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 15))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 20))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
+
+        let json = books.account_statement_json(id1, NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30)).unwrap();
+        let statement: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(id1.to_string(), statement["account"]["id"]);
+        assert_eq!("2022-06-01", statement["from"]);
+        assert_eq!("10000", statement["opening_balance"]);
+        assert_eq!(3, statement["entries"].as_array().unwrap().len());
+        assert_eq!("10000", statement["entries"][1]["balance"]);
+        assert_eq!("20000", statement["closing_balance"]);
+        assert!(books.account_statement_json(Uuid::new_v4(), NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30)).is_err());
+    }
+
+    #[test]
+    fn test_create_interest_schedule() {
+        let mut books = Books::build_empty("My Books");
+        let mut savings = Account::create_new("Savings", AccountType::Asset);
+        savings.starting_balance = dec!(1200);
+        let savings_id = savings.id;
+        books.add_account(savings).unwrap();
+        let interest = Account::create_new("Interest", AccountType::Revenue);
+        let interest_id = interest.id;
+        books.add_account(interest).unwrap();
+
+        books.create_interest_schedule(savings_id, interest_id, dec!(12), ScheduleEnum::Months, NaiveDate::from_ymd(2022, 1, 31)).unwrap();
+        books.generate(NaiveDate::from_ymd(2022, 3, 31), today()).unwrap();
+
+        assert_eq!(3, books.transactions.len());
+        let amounts: Vec<Decimal> = books.transactions.iter().map(|t| t.account_entries(savings_id)[0].amount).collect();
+        assert_eq!(vec![dec!(12.00), dec!(12.12), dec!(12.24)], amounts);
+        assert_eq!(Side::Debit, books.transactions[0].account_entries(savings_id)[0].entry_type);
+        assert_eq!(dec!(12.12), books.transactions[1].account_entries(interest_id)[0].amount);
+        assert_eq!(Side::Credit, books.transactions[1].account_entries(interest_id)[0].entry_type);
+        assert_eq!(dec!(1236.36), books.balance_as_of(savings_id, NaiveDate::from_ymd(2022, 3, 31)).unwrap());
+    }
+
+    #[test]
+    fn test_create_straight_line_depreciation() {
+        let mut books = Books::build_empty("My Books");
+        let asset = Account::create_new("Equipment", AccountType::Asset);
+        let asset_id = asset.id;
+        books.add_account(asset).unwrap();
+        let accumulated = Account::create_new("Accumulated Depreciation", AccountType::Asset);
+        let accumulated_id = accumulated.id;
+        books.add_account(accumulated).unwrap();
+        let expense = Account::create_new("Depreciation", AccountType::Expense);
+        let expense_id = expense.id;
+        books.add_account(expense).unwrap();
+
+        books.create_straight_line_depreciation(asset_id, accumulated_id, expense_id, dec!(1000), dec!(100), 3, NaiveDate::from_ymd(2022, 1, 31)).unwrap();
+        books.generate(NaiveDate::from_ymd(2025, 1, 1), today()).unwrap();
+
+        assert_eq!(36, books.transactions.len());
+        assert_eq!(dec!(25.00), books.transactions[0].account_entries(expense_id)[0].amount);
+        assert_eq!(dec!(25.00), books.transactions[35].account_entries(expense_id)[0].amount);
+        let total: Decimal = books.transactions.iter().map(|t| t.account_entries(expense_id)[0].amount).sum();
+        assert_eq!(dec!(900), total);
+
+        books.clear_projections();
+        books.create_straight_line_depreciation(asset_id, accumulated_id, expense_id, dec!(1000), dec!(0), 1, NaiveDate::from_ymd(2022, 1, 1)).unwrap();
+        let schedule_id = books.schedules()[1].id;
+        books.generate(NaiveDate::from_ymd(2025, 1, 1), today()).unwrap();
+        let amounts: Vec<Decimal> = books.transactions.iter()
+            .filter(|t| t.schedule_id() == Some(schedule_id))
+            .map(|t| t.account_entries(expense_id)[0].amount)
+            .collect();
+        assert_eq!(12, amounts.len());
+        assert_eq!(dec!(83.33), amounts[0]);
+        assert_eq!(dec!(83.37), amounts[11]);
+        assert_eq!(dec!(1000), amounts.iter().sum::<Decimal>());
+    }
+
+    #[test]
+    fn test_missing_schedule_occurrences() {
+        let (mut books, id1, id2) = setup_books();
+        let schedule = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 15));
+        let schedule_id = schedule.id;
+        books.add_schedule(schedule).unwrap();
+        books.generate(NaiveDate::from_ymd(2022, 6, 30), today()).unwrap();
+        assert_eq!(6, books.transactions.len());
+        assert!(books.missing_schedule_occurrences(schedule_id, NaiveDate::from_ymd(2022, 12, 31)).is_empty());
+
+        let march = books.transactions[2].clone();
+        assert_eq!(NaiveDate::from_ymd(2022, 3, 15), march.entries[0].date);
+        books.delete_transaction(&march.id).unwrap();
+
+        assert_eq!(vec![NaiveDate::from_ymd(2022, 3, 15)], books.missing_schedule_occurrences(schedule_id, NaiveDate::from_ymd(2022, 12, 31)));
+        assert!(books.missing_schedule_occurrences(schedule_id, NaiveDate::from_ymd(2022, 2, 28)).is_empty());
     }
 
+    #[test]
+    fn test_schedules_by_next_date() {
+        let (mut books, id1, id2) = setup_books();
+        let mut finished = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 4));
+        finished.end_date = Some(NaiveDate::from_ymd(2022, 1, 31));
+        finished.last_date = Some(NaiveDate::from_ymd(2022, 1, 4));
+        let mut later = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 20));
+        later.last_date = Some(NaiveDate::from_ymd(2022, 5, 20));
+        let sooner = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 1));
+        let ids = [sooner.id, later.id, finished.id];
+        books.add_schedule(finished).unwrap();
+        books.add_schedule(later).unwrap();
+        books.add_schedule(sooner).unwrap();
+
+        let schedules = books.schedules_by_next_date();
+        assert_eq!(ids.to_vec(), schedules.iter().map(|(s, _)| s.id).collect::<Vec<Uuid>>());
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 1), schedules[0].1);
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 20), schedules[1].1);
+        assert_eq!(NaiveDate::from_ymd(2022, 2, 4), schedules[2].1);
+    }
 
     #[test]
-    fn test_add_transaction_no_cr_account() {
-        let (mut books, id1, _) = setup_books();
-        let t1 = build_transaction(Some(id1), None);
-        let t1_id = t1.id;
-        books.add_transaction(t1).unwrap();
-        let t1_2 = &books.transactions()[0];
-        assert_eq!(t1_id, t1_2.id);
+    fn test_trial_balance() {
+        let (mut books, bank_id, savings_id) = setup_books();
+        let mut loan = Account::create_new("Loan", AccountType::Liability);
+        loan.starting_balance = dec!(50);
+        let loan_id = loan.id;
+        books.add_account(loan).unwrap();
+        books.settings.require_double_entry = true;
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(loan_id), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(savings_id), Some(bank_id), NaiveDate::from_ymd(2022, 6, 2))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(loan_id), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
+
+        let rows = books.trial_balance(NaiveDate::from_ymd(2022, 6, 30));
+
+        assert_eq!(vec!["Savings Account 1", "Savings Account 2", "Loan"], rows.iter().map(|r| r.name.as_str()).collect::<Vec<&str>>());
+        assert_eq!((dec!(10000), dec!(10000), dec!(0)), (rows[0].debit_total, rows[0].credit_total, rows[0].balance));
+        assert_eq!(dec!(10000), rows[1].balance);
+        assert_eq!((dec!(0), dec!(10000), dec!(10050)), (rows[2].debit_total, rows[2].credit_total, rows[2].balance));
+        let debits: Decimal = rows.iter().map(|r| r.debit_total).sum();
+        let credits: Decimal = rows.iter().map(|r| r.credit_total).sum();
+        assert_eq!(debits, credits);
     }
 
     #[test]
-    fn test_add_transaction_no_dr_account() {
-        let (mut books, _, id2) = setup_books();
-        let t1 = build_transaction(None, Some(id2));
-        let t1_id = t1.id;
-        books.add_transaction(t1).unwrap();
-        let t1_2 = &books.transactions()[0];
-        assert_eq!(t1_id, t1_2.id);
+    fn test_income_statement() {
+        let (mut books, bank_id, _) = setup_books();
+        let salary = Account::create_new("Salary", AccountType::Revenue);
+        let salary_id = salary.id;
+        books.add_account(salary).unwrap();
+        let rent = Account::create_new("Rent", AccountType::Expense);
+        let rent_id = rent.id;
+        books.add_account(rent).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(salary_id), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(salary_id), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
+        let mut rent_paid = build_transaction_with_date(Some(rent_id), Some(bank_id), NaiveDate::from_ymd(2022, 6, 30));
+        rent_paid.entries.iter_mut().for_each(|e| e.amount = dec!(4000));
+        books.add_transaction(rent_paid).unwrap();
+        let mut projected = build_transaction_with_date(Some(rent_id), Some(bank_id), NaiveDate::from_ymd(2022, 6, 15));
+        projected.status = TransactionStatus::Projected;
+        books.add_transaction(projected).unwrap();
+        let (start, end) = (NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30));
+
+        let statement = books.income_statement(start, end);
+
+        assert_eq!(vec!["Salary", "Rent"], statement.accounts.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>());
+        assert_eq!(dec!(10000), statement.total_revenue);
+        assert_eq!(dec!(4000), statement.total_expense);
+        assert_eq!(dec!(6000), statement.net_income);
+
+        let forecast = books.income_statement_with_projections(start, end, true);
+        assert_eq!(dec!(14000), forecast.total_expense);
+        assert_eq!(dec!(-4000), forecast.net_income);
     }
 
     #[test]
-    fn test_add_transaction_invalid_dr_account() {
-        let (mut books, _, id2) = setup_books();
-        let t1 = build_transaction(Some(Uuid::new_v4()), Some(id2));
-        let _result = books.add_transaction(t1);
-        let expected: Result<(), BooksError> = Err(BooksError { error: "Invalid CR account".to_string() });
-        assert!(matches!(expected, _result));
-        assert_eq!(0, (&books.transactions()).len());
+    fn test_report_by_dimension() {
+        let (mut books, bank_id, _) = setup_books();
+        let travel = Account::create_new("Travel", AccountType::Expense);
+        let travel_id = travel.id;
+        books.add_account(travel).unwrap();
+        for (project, amount, date) in [("X", dec!(300), NaiveDate::from_ymd(2022, 6, 1)), ("Y", dec!(120), NaiveDate::from_ymd(2022, 6, 2)),
+                                        ("X", dec!(80), NaiveDate::from_ymd(2022, 6, 3)), ("X", dec!(1000), NaiveDate::from_ymd(2022, 7, 1))] {
+            let mut t = build_transaction_with_date(Some(travel_id), Some(bank_id), date);
+            t.entries.iter_mut().for_each(|e| e.amount = amount);
+            t.entries[0].dimensions.insert("project".to_string(), project.to_string());
+            books.add_transaction(t).unwrap();
+        }
+        books.add_transaction(build_transaction_with_date(Some(travel_id), Some(bank_id), NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+
+        let totals = books.report_by_dimension("project", NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30));
+        assert_eq!(2, totals.len());
+        assert_eq!(dec!(380), totals["X"]);
+        assert_eq!(dec!(120), totals["Y"]);
+        assert!(books.report_by_dimension("department", NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30)).is_empty());
     }
 
     #[test]
-    fn test_add_transaction_invalid_cr_account() {
-        let (mut books, id1, _) = setup_books();
-        let t1 = build_transaction(Some(id1), Some(Uuid::new_v4()));
-        let _result = books.add_transaction(t1);
-        let expected: Result<(), BooksError> = Err(BooksError { error: "Invalid CR account".to_string() });
-        assert!(matches!(expected, _result));
-        assert_eq!(0, (&books.transactions()).len());
+    fn test_exclude_from_reports() {
+        let (mut books, bank_id, _) = setup_books();
+        let rent = Account::create_new("Rent", AccountType::Expense);
+        let rent_id = rent.id;
+        books.add_account(rent).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+        books.add_transaction(build_transaction_with_date(Some(rent_id), Some(bank_id), date)).unwrap();
+        let mut reclassification = build_transaction_with_date(Some(rent_id), Some(bank_id), date);
+        reclassification.entries.iter_mut().for_each(|e| {
+            e.amount = dec!(500);
+            e.exclude_from_reports = true;
+        });
+        books.add_transaction(reclassification).unwrap();
+
+        let statement = books.income_statement(NaiveDate::from_ymd(2022, 6, 1), date);
+        assert_eq!(dec!(10000), statement.total_expense);
+
+        let rent_row = books.trial_balance(date).into_iter().find(|r| r.account_id == rent_id).unwrap();
+        assert_eq!(dec!(10500), rent_row.debit_total);
+        assert_eq!(dec!(10500), rent_row.balance);
     }
 
     #[test]
-    fn test_add_transaction_no_account() {
-        let (mut books, _id1, _id2) = setup_books();
-        let t1 = build_transaction(None, None);
-        let _result = books.add_transaction(t1);
-        let expected: Result<(), BooksError> = Err(BooksError { error: "A transaction must have at least one account".to_string() });
-        assert!(matches!(expected, _result));
-        assert_eq!(0, (&books.transactions()).len());
+    fn test_balance_sheet() {
+        let mut books = Books::build_empty("My Books");
+        let mut ids = Vec::new();
+        for (name, account_type) in [("Bank", AccountType::Asset), ("Car", AccountType::Asset), ("Loan", AccountType::Liability),
+                                     ("Capital", AccountType::Equity), ("Salary", AccountType::Revenue), ("Rent", AccountType::Expense)] {
+            let account = Account::create_new(name, account_type);
+            ids.push(account.id);
+            books.add_account(account).unwrap();
+        }
+        let date = NaiveDate::from_ymd(2022, 6, 4);
+        for (dr, cr) in [(0, 3), (0, 2), (0, 4)] {
+            books.add_transaction(build_transaction_with_date(Some(ids[dr]), Some(ids[cr]), date)).unwrap();
+        }
+
+        let sheet = books.balance_sheet(date);
+
+        assert_eq!(vec!["Bank", "Car"], sheet.assets.iter().map(|a| a.name.as_str()).collect::<Vec<&str>>());
+        assert_eq!(dec!(0), sheet.assets[1].balance);
+        assert_eq!(dec!(30000), sheet.total_assets);
+        assert_eq!(dec!(10000), sheet.total_liabilities);
+        assert_eq!(dec!(10000), sheet.net_income);
+        assert_eq!(dec!(20000), sheet.total_equity);
+        assert_eq!(sheet.total_assets, sheet.total_liabilities + sheet.total_equity);
+        assert!(serde_json::to_string(&sheet).unwrap().contains("\"total_assets\""));
+        assert!(sheet.category_totals.is_empty());
     }
 
     #[test]
-    fn test_delete_transaction() {
-        let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction(Some(id1), Some(id2));
-        let t1_id = t1.id;
-        books.add_transaction(t1).unwrap();
+    fn test_account_categories() {
+        let mut books = Books::build_empty("My Books");
+        let current = AccountCategory::new("Current Assets", Side::Debit);
+        let fixed = AccountCategory::new("Fixed Assets", Side::Debit);
+        let mut ids = Vec::new();
+        for (name, account_type, category) in [("Bank", AccountType::Asset, Some(current.clone())), ("Car", AccountType::Asset, Some(fixed.clone())),
+                                               ("Savings", AccountType::Asset, Some(current.clone())), ("Loan", AccountType::Liability, None)] {
+            let mut account = Account::create_new(name, account_type);
+            account.category = category;
+            ids.push(account.id);
+            books.add_account(account).unwrap();
+        }
+        let date = NaiveDate::from_ymd(2022, 6, 4);
+        for (dr, cr) in [(0, 3), (1, 3), (2, 3)] {
+            books.add_transaction(build_transaction_with_date(Some(ids[dr]), Some(ids[cr]), date)).unwrap();
+        }
 
-        let _result = books.delete_transaction(&t1_id);
-        let expected: Result<(), BooksError> = Ok(());
-        assert!(matches!(expected, _result));
-        assert_eq!(0, books.transactions.len());
+        let grouped = books.accounts_by_category();
+        let groups: Vec<(Option<String>, Vec<&str>)> = grouped
+            .iter()
+            .map(|(name, accounts)| (name.clone(), accounts.iter().map(|a| a.name.as_str()).collect()))
+            .collect();
+        assert_eq!(vec![(Some("Current Assets".to_string()), vec!["Bank", "Savings"]), (Some("Fixed Assets".to_string()), vec!["Car"]), (None, vec!["Loan"])], groups);
+
+        let sheet = books.balance_sheet(date);
+        assert_eq!(2, sheet.category_totals.len());
+        assert_eq!("Current Assets", sheet.category_totals[0].category);
+        assert_eq!(dec!(20000), sheet.category_totals[0].total);
+        assert_eq!(dec!(10000), sheet.category_totals[1].total);
+
+        let error = books.set_account_category(&ids[3], Some(current)).err().unwrap().error;
+        assert_eq!("Category Current Assets has a Debit normal balance but account Loan has a Credit normal balance.", error);
+        books.set_account_category(&ids[3], Some(AccountCategory::new("Long Term Liabilities", Side::Credit))).unwrap();
+        books.set_account_category(&ids[1], None).unwrap();
+        let categories: Vec<String> = books.balance_sheet(date).category_totals.into_iter().map(|c| c.category).collect();
+        assert_eq!(vec!["Current Assets", "Long Term Liabilities"], categories);
     }
 
     #[test]
-    fn test_delete_invalid_transaction() {
+    fn test_balances_snapshot() {
         let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction(Some(id1), Some(id2));
-        books.add_transaction(t1).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 7, 4))).unwrap();
+        let date = NaiveDate::from_ymd(2022, 6, 30);
 
-        let id = &Uuid::new_v4();
-        let result = books.delete_transaction(&id);
-        assert_eq!(format!("Transaction {} not found.", id  ).as_str(), result.err().unwrap().error);
-        assert_eq!(1, books.transactions.len());
+        let snapshot = books.balances_snapshot(date);
+        assert_eq!(2, snapshot.accounts.len());
+        for a in snapshot.accounts.iter() {
+            assert_eq!(books.balance_as_of(a.id, date).unwrap(), a.balance);
+        }
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(!json.contains("transactions"));
+        assert!(!json.contains('\n'));
+        let round_trip: super::BalancesSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(date, round_trip.as_of);
+        assert_eq!(snapshot.accounts[0].balance, round_trip.accounts[0].balance);
     }
 
-
     #[test]
-    fn test_account_entries() {
+    fn test_reconciliation_difference() {
         let (mut books, id1, id2) = setup_books();
         let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
-        let t2 = build_transaction_with_date(None, Some(id2),NaiveDate::from_ymd(2022, 6, 5));
-        let t3 = build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 7, 1));
-        let t4 = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 7, 2));
-        let t1a1e1 = &t1.account_entries(id1)[0];
-        let t3a1e3 = &t3.account_entries(id1)[0];
-        let t4a1e4 = &t4.account_entries(id1)[0];
-        let t1a2e1 = &t1.account_entries(id2)[0];
-        let t2a2e1 = &t2.account_entries(id2)[0];
-        let t4a2e1 = &t4.account_entries(id2)[0];
+        let t2 = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 10));
+        let t3 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 20));
+        let entry_ids = [t1.entries[0].id, t2.entries[1].id, t3.entries[0].id];
         books.add_transaction(t1).unwrap();
         books.add_transaction(t2).unwrap();
         books.add_transaction(t3).unwrap();
-        books.add_transaction(t4).unwrap();
-        let a1_entries = books.account_entries(id1).unwrap();
-        assert_eq!(3, a1_entries.len());
 
-        let entry1 = &a1_entries[0];
-        assert_eq!(t1a1e1.id, entry1.id);
-        assert_eq!(dec!(10000), entry1.balance.unwrap());
+        let mut session = ReconciliationSession::new(id1, NaiveDate::from_ymd(2022, 6, 30), dec!(10000));
+        assert_eq!(dec!(10000), books.reconciliation_difference(&session).unwrap());
+        session.clear(entry_ids[0]);
+        session.clear(entry_ids[2]);
+        assert_eq!(dec!(-10000), books.reconciliation_difference(&session).unwrap());
+        session.clear(entry_ids[1]);
+        assert_eq!(dec!(0), books.reconciliation_difference(&session).unwrap());
+        session.unclear(&entry_ids[2]);
+        assert_eq!(dec!(10000), books.reconciliation_difference(&session).unwrap());
 
-        let entry2 = &a1_entries[1];
-        assert_eq!(t3a1e3.id, entry2.id);
-        assert_eq!(dec!(20000), entry2.balance.unwrap());
+        books.settings.require_approval = true;
+        let mut unapproved = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 25));
+        unapproved.approved = false;
+        session.clear(unapproved.entries[0].id);
+        books.add_transaction(unapproved).unwrap();
+        assert_eq!(dec!(10000), books.reconciliation_difference(&session).unwrap());
+    }
 
-        let entry3 = &a1_entries[2];
-        println!("{:?}", entry3);
-        assert_eq!(t4a1e4.id, entry3.id);
-        assert_eq!(dec!(10000), entry3.balance.unwrap());
+    #[test]
+    fn test_clear_projections() {
+        let (mut books, id1, id2) = setup_books();
+        let recorded = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
+        let recorded_id = recorded.id;
+        books.add_transaction(recorded).unwrap();
+        books.add_schedule(build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        books.generate(NaiveDate::from_ymd(2022, 9, 30), today()).unwrap();
+        assert_eq!(5, books.transactions.len());
+        assert!(books.schedules()[0].last_date.is_some());
 
-        let a2_entries = books.account_entries(id2).unwrap();
-        assert_eq!(3, a2_entries.len());
+        books.clear_projections();
+        assert_eq!(1, books.transactions.len());
+        assert_eq!(recorded_id, books.transactions[0].id);
+        assert!(books.schedules()[0].last_date.is_none());
+        assert!(books.end_date().is_none());
 
-        let entry21 = &a2_entries[0];
-        assert_eq!(t1a2e1.id, entry21.id);
-        assert_eq!(dec!(-10000), entry21.balance.unwrap());
+        books.generate(NaiveDate::from_ymd(2022, 9, 30), today()).unwrap();
+        assert_eq!(5, books.transactions.len());
+    }
 
-        let entry22 = &a2_entries[1];
-        assert_eq!(t2a2e1.id, entry22.id);
-        assert_eq!(dec!(-20000), entry22.balance.unwrap());
+    #[test]
+    fn test_inactive_schedules() {
+        let (mut books, id1, id2) = setup_books();
+        let mut healthy = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
+        healthy.end_date = Some(NaiveDate::from_ymd(2023, 6, 4));
+        books.add_schedule(healthy).unwrap();
+        let mut expired = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 4));
+        expired.end_date = Some(NaiveDate::from_ymd(2022, 3, 1));
+        expired.last_date = Some(NaiveDate::from_ymd(2022, 2, 4));
+        let expired_id = expired.id;
+        books.add_schedule(expired).unwrap();
 
-        let entry23 = &a2_entries[2];
-        assert_eq!(t4a2e1.id, entry23.id);
-        assert_eq!(dec!(-10000), entry23.balance.unwrap());
+        let inactive = books.inactive_schedules();
+        assert_eq!(1, inactive.len());
+        assert_eq!(expired_id, inactive[0].0);
+        assert_eq!("Next date 2022-03-04 is after end date 2022-03-01", inactive[0].1);
+    }
+
+    #[test]
+    fn test_generate() {
+        let (mut books, id1, id2) = setup_books();
+        let _result = books.add_schedule(
+            build_schedule(id1, id2, NaiveDate::from_ymd(2022, 3, 11), "S_1", "st test 1", dec!(100.99), 3, ScheduleEnum::Months)
+        );
+
+        let _result = books.add_schedule(
+            build_schedule(id2, id1, NaiveDate::from_ymd(2022, 3, 11), "S_2", "st test 2", dec!(20.23), 45, ScheduleEnum::Days)
+        );
 
+        assert_eq!(0, books.transactions.len());
+        books.generate(NaiveDate::from_ymd(2023, 3, 11), today()).unwrap();
 
+        assert_eq!(14, books.transactions.len());
+        assert_eq!("st test 2", books.transactions[2].entries[0].description);
+        assert_eq!("st test 1", books.transactions[4].entries[0].description);
     }
 
     #[test]
-    fn test_account_transaction() {
+    fn test_generate_business_day_past_end_date() {
         let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
-        let t1a1e1 = &t1.account_entries(id1)[0];
-        let _t1a2e1 = &t1.account_entries(id2)[0];
-        books.add_transaction(t1).unwrap();
-        let a1_entries = books.account_transactions(id1).unwrap();
-        assert_eq!(1, a1_entries.len());
+        // October 1st is a Saturday, so the first schedule would post on the 3rd, after the end date.
+        let mut adjusted = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 9, 1));
+        adjusted.business_day_rule = BusinessDayAdjustment::Following;
+        let adjusted_id = adjusted.id;
+        books.add_schedule(adjusted).unwrap();
+        let daily = build_schedule(id2, id1, NaiveDate::from_ymd(2022, 9, 30), "Daily", "Daily", dec!(5), 1, ScheduleEnum::Days);
+        let daily_id = daily.id;
+        books.add_schedule(daily).unwrap();
 
-        let entry1 = &a1_entries[0].account_entries(id1)[0];
-        assert_eq!(t1a1e1.id, entry1.id);
-        assert_eq!(dec!(10000), entry1.balance.unwrap());
+        books.generate(NaiveDate::from_ymd(2022, 10, 2), today()).unwrap();
+
+        let dates = |id: Uuid| books.transactions().iter()
+            .filter(|t| t.schedule_id() == Some(id))
+            .map(|t| t.date().unwrap())
+            .collect::<Vec<NaiveDate>>();
+        assert_eq!(vec![NaiveDate::from_ymd(2022, 9, 1)], dates(adjusted_id));
+        assert_eq!(vec![NaiveDate::from_ymd(2022, 9, 30), NaiveDate::from_ymd(2022, 10, 1), NaiveDate::from_ymd(2022, 10, 2)], dates(daily_id));
     }
 
+    #[test]
+    fn test_generate_projection_horizon() {
+        let (mut books, id1, id2) = setup_books();
+        books.settings.max_projection_years = 1;
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2040, 1, 15))).unwrap();
+        books.add_schedule(build_schedule_std(id1, id2, NaiveDate::from_ymd(2040, 2, 1))).unwrap();
+        assert_eq!(NaiveDate::from_ymd(2041, 1, 15), books.projection_horizon(today()));
+        assert_eq!(NaiveDate::from_ymd(2042, 3, 1), books.projection_horizon(NaiveDate::from_ymd(2041, 3, 1)));
 
+        assert!(books.generate(NaiveDate::from_ymd(2041, 1, 15), today()).is_ok());
+        let count = books.transactions.len();
+        assert!(count > 1);
+
+        let error = books.generate(NaiveDate::from_ymd(2045, 6, 30), today()).err().unwrap().error;
+        assert_eq!("End date 2045-06-30 is beyond the max_projection_years horizon of 2041-01-15.", error);
+        assert_eq!(count, books.transactions.len());
+    }
 
     #[test]
-    fn test_account_transactions() {
+    fn test_repair_dangling_entries() {
         let (mut books, id1, id2) = setup_books();
-        let t1 = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4));
-        let t2 = build_transaction_with_date(None, Some(id2),NaiveDate::from_ymd(2022, 6, 5));
-        let t3 = build_transaction_with_date(Some(id1), None, NaiveDate::from_ymd(2022, 7, 1));
-        let t4 = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 7, 2));
-        let t1a1e1 = &t1.account_entries(id1)[0];
-        let t3a1e3 = &t3.account_entries(id1)[0];
-        let t4a1e4 = &t4.account_entries(id1)[0];
-        let t1a2e1 = &t1.account_entries(id2)[0];
-        let t2a2e1 = &t2.account_entries(id2)[0];
-        let t4a2e1 = &t4.account_entries(id2)[0];
-        books.add_transaction(t1).unwrap();
-        books.add_transaction(t2).unwrap();
-        books.add_transaction(t3).unwrap();
-        books.add_transaction(t4).unwrap();
-        let a1_entries = books.account_transactions(id1).unwrap();
-        assert_eq!(3, a1_entries.len());
+        books.add_transaction(build_transaction(Some(id1), Some(id2))).unwrap();
+        let missing_id = Uuid::new_v4();
+        let dangling = build_transaction(Some(missing_id), Some(id2));
+        let dangling_id = dangling.id;
+        books.insert_sorted(dangling);
+        let fallback = Account::create_new("Suspense", AccountType::Asset);
+        let fallback_id = fallback.id;
+        books.add_account(fallback).unwrap();
 
-        let entry1 = &a1_entries[0].account_entries(id1)[0];
-        assert_eq!(t1a1e1.id, entry1.id);
-        assert_eq!(dec!(10000), entry1.balance.unwrap());
+        assert_eq!(0, books.repair_dangling_entries(Uuid::new_v4()));
+        assert_eq!(1, books.repair_dangling_entries(fallback_id));
 
-        let entry2 = &a1_entries[1].account_entries(id1)[0];
-        assert_eq!(t3a1e3.id, entry2.id);
-        assert_eq!(dec!(20000), entry2.balance.unwrap());
+        let repaired = books.transaction(dangling_id).unwrap();
+        assert_eq!(fallback_id, repaired.entries[0].account_id);
+        assert_eq!(id2, repaired.entries[1].account_id);
+        assert!(books.transactions().iter().flat_map(|t| t.entries.iter()).all(|e| e.account_id != missing_id));
+        assert_eq!(0, books.repair_dangling_entries(fallback_id));
+    }
 
-        let entry3 = &a1_entries[2].account_entries(id1)[0];
-        println!("{:?}", entry3);
-        assert_eq!(t4a1e4.id, entry3.id);
-        assert_eq!(dec!(10000), entry3.balance.unwrap());
+    #[test]
+    fn test_generate_skips_missing_accounts() {
+        let (mut books, id1, id2) = setup_books();
+        let valid = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 1, 15));
+        let valid_id = valid.id;
+        books.add_schedule(valid).unwrap();
+        let missing_id = Uuid::new_v4();
+        let dangling = build_schedule_std(id1, missing_id, NaiveDate::from_ymd(2022, 1, 15));
+        let dangling_id = dangling.id;
+        books.scheduler.add_schedule(dangling);
 
-        let a2_entries = books.account_transactions(id2).unwrap();
-        assert_eq!(3, a2_entries.len());
+        let report = books.generate(NaiveDate::from_ymd(2022, 3, 31), today()).unwrap();
 
-        let entry21 = &a2_entries[0].account_entries(id2)[0];
-        assert_eq!(t1a2e1.id, entry21.id);
-        assert_eq!(dec!(-10000), entry21.balance.unwrap());
+        assert_eq!(vec![(dangling_id, format!("Account {} not found", missing_id))], report.skipped);
+        assert!(books.transactions().iter().all(|t| t.schedule_id() == Some(valid_id)));
+        assert!(!books.transactions().is_empty());
+        assert_eq!(None, books.schedules()[1].last_date);
+    }
 
-        let entry22 = &a2_entries[1].account_entries(id2)[0];
-        assert_eq!(t2a2e1.id, entry22.id);
-        assert_eq!(dec!(-20000), entry22.balance.unwrap());
+    #[test]
+    fn test_balances_as_of() {
+        let (mut books, id1, id2) = setup_books();
+        let id3 = Uuid::new_v4();
+        let mut a3 = Account::create_new("Loan 1", AccountType::Liability);
+        a3.id = id3;
+        a3.starting_balance = dec!(500);
+        books.add_account(a3).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id2), Some(id3), NaiveDate::from_ymd(2022, 6, 10))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id3), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
 
-        let entry23 = &a2_entries[2].account_entries(id2)[0];
-        assert_eq!(t4a2e1.id, entry23.id);
-        assert_eq!(dec!(-10000), entry23.balance.unwrap());
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+        let balances = books.balances_as_of(date);
+        assert_eq!(3, balances.len());
+        for id in [id1, id2, id3] {
+            assert_eq!(books.balance_as_of(id, date).unwrap(), balances[&id]);
+        }
+        assert_eq!(dec!(10000), balances[&id1]);
+        assert_eq!(dec!(0), balances[&id2]);
+        assert_eq!(dec!(10500), balances[&id3]);
+    }
+
+    #[test]
+    fn test_apply_cleared_csv() {
+        let (mut books, id1, id2) = setup_books();
+        let ids: Vec<Uuid> = (1..=3).map(|day| {
+            let transaction = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, day));
+            let id = transaction.id;
+            books.add_transaction(transaction).unwrap();
+            id
+        }).collect();
+        let second_entry = books.transaction(ids[1]).unwrap().account_entries(id1)[0].id;
+        books.transactions[2].entries[0].dimensions.insert(REFERENCE_DIMENSION.to_string(), "REF-12".to_string());
+
+        let cleared = format!("id,cleared\n{},true\n{},yes\n", ids[0], second_entry);
+        assert_eq!(3, books.apply_cleared_csv(&cleared).unwrap());
+        assert!(books.transaction(ids[0]).unwrap().entries.iter().all(|e| e.reconciled));
+        assert_eq!(vec![ids[2]], books.unreconciled_entries(id1).iter().map(|e| e.transaction_id).collect::<Vec<Uuid>>());
 
+        let missing = Uuid::new_v4();
+        let with_unknown = format!("id,cleared\nREF-12,true\n{},true\nREF-13,false\n", missing);
+        assert_eq!(format!("Unknown ids: {} (row 3), REF-13 (row 4)", missing), books.apply_cleared_csv(&with_unknown).err().unwrap().error);
+        assert!(books.transaction(ids[2]).unwrap().entries.iter().all(|e| !e.reconciled));
+        assert_eq!(2, books.apply_cleared_csv("id,cleared\nREF-12,true\n").unwrap());
+        assert!(books.transaction(ids[2]).unwrap().entries.iter().all(|e| e.reconciled));
 
+        let uncleared = format!("id,cleared\n{},0\n", ids[0]);
+        assert_eq!(2, books.apply_cleared_csv(&uncleared).unwrap());
+        assert_eq!(1, books.unreconciled_entries(id1).len());
+        assert!(books.apply_cleared_csv(&format!("id,cleared\n{},maybe\n", ids[0])).is_err());
     }
 
     #[test]
-    fn test_add_schedule() {
+    fn test_export_account_csv() {
         let (mut books, id1, id2) = setup_books();
-        let st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
-        let _result = books.add_schedule(st1);
-        let expected: Result<(), BooksError> = Err(BooksError { error: "Invalid CR account".to_string() });
-        assert!(matches!(expected, _result));
-        assert_eq!(1, (&books.schedules()).len());
+        let mut pay = build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 6, 1));
+        pay.entries.iter_mut().for_each(|e| e.description = "Pay, June".to_string());
+        books.add_transaction(pay).unwrap();
+        let mut supermarket = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 2));
+        supermarket.entries.iter_mut().for_each(|e| { e.description = "Supermarket".to_string(); e.amount = dec!(82.50) });
+        books.add_transaction(supermarket).unwrap();
+        let mut transfer = build_transaction_with_date(Some(id2), Some(id1), NaiveDate::from_ymd(2022, 6, 3));
+        transfer.entries.iter_mut().for_each(|e| { e.description = "Transfer".to_string(); e.amount = dec!(20000) });
+        books.add_transaction(transfer).unwrap();
+
+        let mut output: Vec<u8> = Vec::new();
+        books.export_account_csv(id1, &mut output).unwrap();
+
+        let expected = "date,description,debit,credit,balance\n\
+            2022-06-01,\"Pay, June\",10000.00,,10000.00\n\
+            2022-06-02,Supermarket,,82.50,9917.50\n\
+            2022-06-03,Transfer,,20000.00,(10082.50)\n";
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+        let missing = Uuid::new_v4();
+        assert_eq!(format!("Account not found for id {}", missing), books.export_account_csv(missing, Vec::<u8>::new()).err().unwrap().error);
     }
 
     #[test]
-    fn test_update_schedule() {
-        let (mut books, id1, id2) = setup_books();
-        let st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
-        let mut st1_copy = st1.clone();
-        let _result = books.add_schedule(st1);
+    fn test_roll_forward() {
+        let (mut books, bank_id, _) = setup_books();
+        let mut ids = HashMap::new();
+        for (name, account_type) in [("Loan", AccountType::Liability), ("Capital", AccountType::Equity), ("Retained Earnings", AccountType::Equity),
+                                     ("Salary", AccountType::Revenue), ("Rent", AccountType::Expense)] {
+            let account = Account::create_new(name, account_type);
+            ids.insert(name, account.id);
+            books.add_account(account).unwrap();
+        }
+        books.accounts.get_mut(&bank_id).unwrap().starting_balance_date = Some(NaiveDate::from_ymd(2022, 1, 1));
+        books.add_schedule(build_schedule_std(ids["Rent"], bank_id, NaiveDate::from_ymd(2022, 11, 1))).unwrap();
+        let year_end = NaiveDate::from_ymd(2022, 12, 31);
+        for (dr, cr) in [(bank_id, ids["Loan"]), (bank_id, ids["Capital"]), (bank_id, ids["Salary"])] {
+            books.add_transaction(build_transaction_with_date(Some(dr), Some(cr), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+        }
+        let mut rent = build_transaction_with_date(Some(ids["Rent"]), Some(bank_id), NaiveDate::from_ymd(2022, 6, 1));
+        rent.entries.iter_mut().for_each(|e| e.amount = dec!(4000));
+        books.add_transaction(rent).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(ids["Loan"]), NaiveDate::from_ymd(2023, 1, 5))).unwrap();
 
-        st1_copy.entries[0].description = "test changed".to_string();
-        let _result = books.update_schedule(st1_copy);
-        assert_eq!(1, (books.schedules()).len());
-        assert_eq!("test changed", books.schedules()[0].entries[0].description);
+        let next_year = books.roll_forward(year_end, "2023");
+
+        assert_eq!("2023", next_year.name);
+        assert_ne!(books.id, next_year.id);
+        assert!(next_year.transactions().is_empty());
+        assert_eq!(books.accounts().len(), next_year.accounts().len());
+        let starting = |id: Uuid| next_year.accounts[&id].starting_balance;
+        assert_eq!(dec!(26000), starting(bank_id));
+        assert_eq!(dec!(10000), starting(ids["Loan"]));
+        assert_eq!(dec!(10000), starting(ids["Capital"]));
+        assert_eq!(dec!(6000), starting(ids["Retained Earnings"]));
+        assert_eq!(dec!(0), starting(ids["Salary"]));
+        assert_eq!(dec!(0), starting(ids["Rent"]));
+        assert!(next_year.accounts.values().all(|a| a.starting_balance_date.is_none()));
+
+        let (debits, credits) = next_year.trial_balance(year_end).iter().fold((Decimal::ZERO, Decimal::ZERO), |(dr, cr), r| {
+            match r.account_type.normal_balance() {
+                Side::Debit => (dr + r.balance, cr),
+                Side::Credit => (dr, cr + r.balance),
+            }
+        });
+        assert_eq!(debits, credits);
+
+        let schedule = &next_year.schedules()[0];
+        assert_eq!(Some(NaiveDate::from_ymd(2022, 12, 1)), schedule.last_date);
+        assert_eq!(2, schedule.occurrence_count);
+        let mut next_year = next_year;
+        next_year.generate(NaiveDate::from_ymd(2023, 2, 28), today()).unwrap();
+        assert_eq!(vec![NaiveDate::from_ymd(2023, 1, 1), NaiveDate::from_ymd(2023, 2, 1)],
+            next_year.transactions().iter().filter_map(|t| t.date()).collect::<Vec<NaiveDate>>());
     }
 
+    #[test]
+    fn test_roll_forward_creates_retained_earnings() {
+        let (mut books, bank_id, _) = setup_books();
+        let salary = Account::create_new("Salary", AccountType::Revenue);
+        let salary_id = salary.id;
+        books.add_account(salary).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(bank_id), Some(salary_id), NaiveDate::from_ymd(2022, 6, 1))).unwrap();
+
+        let next_year = books.roll_forward(NaiveDate::from_ymd(2022, 12, 31), "2023");
+
+        assert_eq!(books.accounts().len() + 1, next_year.accounts().len());
+        let retained = next_year.accounts().into_iter().find(|a| a.name == RETAINED_EARNINGS).unwrap();
+        assert!(retained.account_type == AccountType::Equity);
+        assert_eq!(dec!(10000), retained.starting_balance);
+        assert_eq!(dec!(0), next_year.accounts[&salary_id].starting_balance);
+    }
 
     #[test]
-    fn test_add_schedule_invalid_dr_account() {
+    fn test_total_by_type() {
         let (mut books, id1, id2) = setup_books();
-        let st1 = build_schedule_std(id1, id2, NaiveDate::from_ymd(2022, 6, 4));
-        let _result = books.add_schedule(st1);
-        let expected: Result<(), BooksError> = Ok(());
-        assert!(matches!(expected, _result));
-        assert_eq!(1, (&books.schedules()).len());
+        let salary = Account::create_new("Salary", AccountType::Revenue);
+        let salary_id = salary.id;
+        books.add_account(salary).unwrap();
+        books.accounts.get_mut(&id2).unwrap().starting_balance = dec!(250);
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(salary_id), NaiveDate::from_ymd(2022, 6, 4))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(salary_id), NaiveDate::from_ymd(2022, 7, 4))).unwrap();
+
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+        assert_eq!(dec!(10250), books.total_by_type(AccountType::Asset, date));
+        assert_eq!(dec!(10000), books.total_by_type(AccountType::Revenue, date));
+        assert_eq!(dec!(0), books.total_by_type(AccountType::Liability, date));
     }
 
     #[test]
-    fn test_add_schedule_invalid_cr_account() {
-        let (mut books, id1, _) = setup_books();
-        let st1 = build_schedule_std(id1, Uuid::new_v4(), NaiveDate::from_ymd(2022, 6, 4));
-        let _result = books.add_schedule(st1);
-        let expected: Result<(), BooksError> = Err(BooksError { error: "Invalid CR account".to_string() });
-        assert!(matches!(expected, _result));
-        assert_eq!(0, (&books.schedules()).len());
+    fn test_net_change() {
+        let (mut books, id1, id2) = setup_books();
+        books.accounts.get_mut(&id1).unwrap().starting_balance = dec!(500);
+        for (dr, cr, date) in [(id1, id2, NaiveDate::from_ymd(2022, 5, 31)), (id1, id2, NaiveDate::from_ymd(2022, 6, 1)),
+                               (id2, id1, NaiveDate::from_ymd(2022, 6, 15)), (id1, id2, NaiveDate::from_ymd(2022, 6, 30)),
+                               (id1, id2, NaiveDate::from_ymd(2022, 7, 1))] {
+            books.add_transaction(build_transaction_with_date(Some(dr), Some(cr), date)).unwrap();
+        }
+        let (from, to) = (NaiveDate::from_ymd(2022, 6, 1), NaiveDate::from_ymd(2022, 6, 30));
+
+        assert_eq!(dec!(10000), books.net_change(id1, from, to).unwrap());
+        assert_eq!(dec!(-10000), books.net_change(id2, from, to).unwrap());
+        assert_eq!(dec!(-10000), books.net_change(id1, NaiveDate::from_ymd(2022, 6, 2), NaiveDate::from_ymd(2022, 6, 29)).unwrap());
+        assert!(books.net_change(Uuid::new_v4(), from, to).is_err());
     }
 
     #[test]
-    fn test_generate() {
+    fn test_starting_balance_date() {
         let (mut books, id1, id2) = setup_books();
-        let _result = books.add_schedule(
-            build_schedule(id1, id2, NaiveDate::from_ymd(2022, 3, 11), "S_1", "st test 1", dec!(100.99), 3, ScheduleEnum::Months)
-        );
+        let account = books.accounts.get_mut(&id1).unwrap();
+        account.starting_balance = dec!(2500);
+        account.starting_balance_date = Some(NaiveDate::from_ymd(2022, 7, 1));
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 3, 1))).unwrap();
+        books.add_transaction(build_transaction_with_date(Some(id1), Some(id2), NaiveDate::from_ymd(2022, 7, 1))).unwrap();
 
-        let _result = books.add_schedule(
-            build_schedule(id2, id1, NaiveDate::from_ymd(2022, 3, 11), "S_2", "st test 2", dec!(20.23), 45, ScheduleEnum::Days)
-        );
+        let date = NaiveDate::from_ymd(2022, 12, 31);
+        assert_eq!(dec!(12500), books.balance_as_of(id1, date).unwrap());
+        assert_eq!(dec!(12500), books.balances_as_of(date)[&id1]);
+        assert_eq!(dec!(2500), books.balance_as_of(id1, NaiveDate::from_ymd(2022, 6, 30)).unwrap());
+        assert_eq!(dec!(-20000), books.balance_as_of(id2, date).unwrap());
+        assert_eq!(dec!(10000), books.net_change(id1, NaiveDate::from_ymd(2022, 1, 1), date).unwrap());
 
-        assert_eq!(0, books.transactions.len());
-        books.generate(NaiveDate::from_ymd(2023, 3, 11));
+        let entries = books.account_entries(id1).unwrap();
+        assert_eq!(vec![None, Some(dec!(12500))], entries.iter().map(|e| e.balance).collect::<Vec<Option<Decimal>>>());
+        books.recompute_entry_balances();
+        assert_eq!(None, books.transactions[0].entries[0].balance);
+        assert_eq!(Some(dec!(12500)), books.transactions[1].entries[0].balance);
 
-        assert_eq!(14, books.transactions.len());
-        assert_eq!("st test 2", books.transactions[2].entries[0].description);
-        assert_eq!("st test 1", books.transactions[4].entries[0].description);
+        let row = books.trial_balance(date).into_iter().find(|r| r.account_id == id1).unwrap();
+        assert_eq!((dec!(10000), dec!(0), dec!(12500)), (row.debit_total, row.credit_total, row.balance));
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd(2022, 1, 1)
     }
 
     fn setup_books() -> (Books, Uuid, Uuid) {
         let mut books = Books::build_empty("My Books");
         let dr_account1 = Account::create_new("Savings Account 1", AccountType::Asset);
         let id1: Uuid = dr_account1.id;
-        books.add_account(dr_account1);
+        books.add_account(dr_account1).unwrap();
         let cr_account1 = Account::create_new("Savings Account 2", AccountType::Asset);
         let id2: Uuid = cr_account1.id;
-        books.add_account(cr_account1);
+        books.add_account(cr_account1).unwrap();
         (books, id1, id2)
     }
 
@@ -641,17 +4248,20 @@ mod tests {
             id: transaction_id,
             entries: Vec::new(),
             status: TransactionStatus::Recorded,
-            schedule_id: None
+            source: TransactionSource::Manual,
+            kind: TransactionKind::Normal,
+            approved: true,
+            related: Vec::new(),
         };
 
         if dr_account_id.is_some() {
             t1.entries.push(Entry{id:Uuid::new_v4(),transaction_id,date,description: description_str.to_string(),account_id:dr_account_id.unwrap(),
-                entry_type:Side::Debit, amount,balance:None })
+                entry_type:Side::Debit, amount,balance:None,currency:None,foreign_amount:None,reconciled:false,exclude_from_reports:false,dimensions:HashMap::new() })
         }
 
         if cr_account_id.is_some() {
             t1.entries.push(Entry{id:Uuid::new_v4(),transaction_id,date,description: description_str.to_string(),account_id:cr_account_id.unwrap(),
-                entry_type:Side::Credit,amount,balance:None })
+                entry_type:Side::Credit,amount,balance:None,currency:None,foreign_amount:None,reconciled:false,exclude_from_reports:false,dimensions:HashMap::new() })
         }
         t1
     }
@@ -668,6 +4278,15 @@ mod tests {
             start_date,
             end_date: None,
             last_date: None,
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: None,
+            active: true,
+            notes: None,
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
             frequency,
             period,
             entries: vec![
@@ -677,6 +4296,7 @@ mod tests {
                         account_id: id1,
                         entry_type: Side::Debit,
                         schedule_id: s_id_1,
+                        step_schedule: StepSchedule::default(),
                     },
                     ScheduleEntry {
                         amount,
@@ -684,6 +4304,7 @@ mod tests {
                         account_id: id2,
                         entry_type: Side::Credit,
                         schedule_id: s_id_1,
+                        step_schedule: StepSchedule::default(),
                     }
                 ]
         }
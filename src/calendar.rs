@@ -0,0 +1,121 @@
+//! Business day calendars, for moving dates that fall on weekends or holidays.
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::{Serialize, Deserialize};
+
+use crate::serializer::*;
+
+/// Where a date that isn't a business day moves to.
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+pub enum BusinessDayAdjustment {
+    /// Leave the date as is.
+    #[default]
+    None,
+    /// The next business day.
+    Following,
+    /// The previous business day.
+    Preceding,
+    /// The next business day, unless that is in the next month, then the previous business day.
+    ModifiedFollowing,
+}
+
+impl BusinessDayAdjustment {
+    /// Move the date to a business day, where business days are weekdays that aren't in the holidays.
+    pub fn adjust(self, date: NaiveDate, holidays: &[NaiveDate]) -> NaiveDate {
+        adjust(date, self, holidays)
+    }
+}
+
+/// Business days are weekdays that aren't holidays.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct Calendar {
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_vec_naivedate")]
+    #[serde(deserialize_with = "deserialize_vec_naivedate")]
+    pub holidays: Vec<NaiveDate>,
+    pub adjustment: BusinessDayAdjustment,
+}
+
+impl Calendar {
+    pub fn new(adjustment: BusinessDayAdjustment) -> Calendar {
+        Calendar { holidays: Vec::new(), adjustment }
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        is_business_day(date, &self.holidays)
+    }
+
+    /// Move the date to a business day according to the calendar's adjustment.
+    pub fn adjust(&self, date: NaiveDate) -> NaiveDate {
+        adjust(date, self.adjustment, &self.holidays)
+    }
+}
+
+fn is_business_day(date: NaiveDate, holidays: &[NaiveDate]) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !holidays.contains(&date)
+}
+
+fn adjust(date: NaiveDate, adjustment: BusinessDayAdjustment, holidays: &[NaiveDate]) -> NaiveDate {
+    match adjustment {
+        BusinessDayAdjustment::None => date,
+        BusinessDayAdjustment::Following => step_to_business_day(date, holidays, NaiveDate::succ_opt),
+        BusinessDayAdjustment::Preceding => step_to_business_day(date, holidays, NaiveDate::pred_opt),
+        BusinessDayAdjustment::ModifiedFollowing => {
+            let following = step_to_business_day(date, holidays, NaiveDate::succ_opt);
+            if following.month() == date.month() {
+                following
+            } else {
+                step_to_business_day(date, holidays, NaiveDate::pred_opt)
+            }
+        },
+    }
+}
+
+fn step_to_business_day(date: NaiveDate, holidays: &[NaiveDate], step: fn(&NaiveDate) -> Option<NaiveDate>) -> NaiveDate {
+    let mut adjusted = date;
+    while !is_business_day(adjusted, holidays) {
+        adjusted = match step(&adjusted) {
+            Some(d) => d,
+            None => return date,
+        };
+    }
+    adjusted
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{BusinessDayAdjustment, Calendar};
+
+    #[test]
+    fn test_adjust() {
+        let saturday = NaiveDate::from_ymd(2022, 6, 11);
+        let mut calendar = Calendar::new(BusinessDayAdjustment::Following);
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 13), calendar.adjust(saturday));
+        calendar.holidays.push(NaiveDate::from_ymd(2022, 6, 13));
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 14), calendar.adjust(saturday));
+
+        calendar.adjustment = BusinessDayAdjustment::Preceding;
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 10), calendar.adjust(saturday));
+        calendar.adjustment = BusinessDayAdjustment::None;
+        assert_eq!(saturday, calendar.adjust(saturday));
+    }
+
+    #[test]
+    fn test_modified_following() {
+        let calendar = Calendar::new(BusinessDayAdjustment::ModifiedFollowing);
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 13), calendar.adjust(NaiveDate::from_ymd(2022, 6, 11)));
+        // Saturday the 30th would move into May, so it moves back to Friday instead.
+        assert_eq!(NaiveDate::from_ymd(2022, 4, 29), calendar.adjust(NaiveDate::from_ymd(2022, 4, 30)));
+    }
+
+    #[test]
+    fn test_adjust_with_holidays() {
+        let holidays = [NaiveDate::from_ymd(2022, 6, 13)];
+        let saturday = NaiveDate::from_ymd(2022, 6, 11);
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 14), BusinessDayAdjustment::Following.adjust(saturday, &holidays));
+        assert_eq!(NaiveDate::from_ymd(2022, 6, 10), BusinessDayAdjustment::Preceding.adjust(saturday, &holidays));
+        assert_eq!(saturday, BusinessDayAdjustment::None.adjust(saturday, &holidays));
+        assert_eq!(NaiveDate::from_ymd(2022, 4, 29), BusinessDayAdjustment::ModifiedFollowing.adjust(NaiveDate::from_ymd(2022, 4, 30), &[]));
+    }
+}
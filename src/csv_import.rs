@@ -0,0 +1,379 @@
+//! Import of bank activity from CSV, either with the columns to use given by a mapping or in a fixed
+//! layout for one bank account, and the CSV field handling shared with the exports.
+use std::collections::HashMap;
+use std::io::Read;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::account::{AccountType, Entry, Side, Transaction, TransactionKind, TransactionSource, TransactionStatus};
+use crate::books::{Books, BooksError};
+use crate::format::parse_amount;
+use crate::serializer::parse_date_str;
+
+/// Name of the account imported transactions are posted against when a row doesn't give one.
+pub const UNCATEGORIZED: &str = "Uncategorized";
+
+/// Names of the header columns holding each part of a transaction. The account columns hold account ids,
+/// or are blank for the Uncategorized account.
+#[derive(Clone, Debug)]
+pub struct CsvMapping {
+    pub date: String,
+    pub description: String,
+    pub amount: String,
+    pub debit_account: String,
+    pub credit_account: String,
+}
+
+/// What an import did: the number of transactions imported and the rows that weren't, as (row number
+/// counting the header as row 1, reason).
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: Vec<(usize, String)>,
+}
+
+/// Date, description and amount read from a CSV row.
+pub(crate) struct CsvRow {
+    pub date: NaiveDate,
+    pub description: String,
+    pub amount: Decimal,
+}
+
+impl CsvRow {
+    /// Read the row's date (YYYY-MM-DD), description and amount from the fields at the given positions.
+    pub(crate) fn parse(fields: &[String], date: usize, description: usize, amount: usize) -> Result<CsvRow, String> {
+        let field = |i: usize, name: &str| match fields.get(i) {
+            Some(f) => Ok(f.trim().to_string()),
+            None => Err(format!("missing {}", name)),
+        };
+        let date_str = field(date, "date")?;
+        Ok(CsvRow {
+            date: parse_date_str(&date_str).map_err(|_| format!("bad date {}", date_str))?,
+            description: field(description, "description")?,
+            amount: parse_amount(&field(amount, "amount")?).map_err(|e| e.error)?,
+        })
+    }
+
+    /// A recorded transaction for the row from an import batch, with an entry for the amount (made
+    /// positive) on each side.
+    pub(crate) fn to_transaction(&self, debit_account: Uuid, credit_account: Uuid, batch_id: Uuid) -> Transaction {
+        let transaction_id = Uuid::new_v4();
+        let entry = |account_id, entry_type| Entry {
+            id: Uuid::new_v4(), transaction_id, date: self.date, description: self.description.clone(), account_id, entry_type,
+            amount: self.amount.abs(), balance: None, currency: None, foreign_amount: None, reconciled: false, exclude_from_reports: false,
+            dimensions: HashMap::new(),
+        };
+        Transaction {
+            id: transaction_id,
+            entries: vec![entry(debit_account, Side::Debit), entry(credit_account, Side::Credit)],
+            status: TransactionStatus::Recorded,
+            source: TransactionSource::Import { batch_id },
+            kind: TransactionKind::Normal,
+            approved: true,
+            related: Vec::new(),
+        }
+    }
+}
+
+/// The Uncategorized expense account for rows without a counter account, created the first time a row
+/// needs it.
+struct Uncategorized {
+    id: Option<Uuid>,
+    created: bool,
+}
+
+impl Uncategorized {
+    fn find(books: &Books) -> Uncategorized {
+        let id = books.accounts().iter().find(|a| a.name == UNCATEGORIZED).map(|a| a.id);
+        Uncategorized { id, created: false }
+    }
+
+    /// The account, or the Uncategorized account if there isn't one.
+    fn or_account(&mut self, books: &mut Books, account: Option<Uuid>) -> Result<Uuid, BooksError> {
+        if let Some(id) = account.or(self.id) {
+            return Ok(id)
+        }
+        let id = books.create_account(UNCATEGORIZED, AccountType::Expense)?.id;
+        self.id = Some(id);
+        self.created = true;
+        Ok(id)
+    }
+
+    /// Delete the account again if it was created by the import but nothing was posted to it.
+    fn remove_if_unused(&self, books: &mut Books) -> Result<(), BooksError> {
+        match self.id {
+            Some(id) if self.created && !books.transactions().iter().any(|t| t.involves_account(&id)) => books.delete_account(&id),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Import transactions from CSV with a header row, dates as YYYY-MM-DD. Each row becomes a two entry
+/// transaction debiting and crediting the mapped accounts; a negative amount swaps them. A blank account
+/// is the Uncategorized expense account, which is created if missing. Rows that can't be read or are
+/// rejected by the books are skipped, and listed in the report, while the others are still imported.
+/// Fails only if the CSV can't be read or a mapped column is missing.
+pub fn import_transactions<R: Read>(books: &mut Books, mut reader: R, mapping: CsvMapping) -> Result<ImportReport, BooksError> {
+    let mut csv = String::new();
+    reader.read_to_string(&mut csv).map_err(|e| BooksError { error: format!("Failed to read CSV: {}", e) })?;
+    let mut lines = csv.lines();
+    let header: Vec<String> = match lines.next() {
+        Some(line) => csv_fields(line).iter().map(|f| f.trim().to_string()).collect(),
+        None => return Ok(ImportReport::default()),
+    };
+    let column = |name: &String| match header.iter().position(|h| h == name) {
+        Some(i) => Ok(i),
+        None => Err(BooksError::from_str(format!("Column {} not found", name).as_str())),
+    };
+    let (date, description, amount) = (column(&mapping.date)?, column(&mapping.description)?, column(&mapping.amount)?);
+    let (debit_column, credit_column) = (column(&mapping.debit_account)?, column(&mapping.credit_account)?);
+
+    let batch_id = Uuid::new_v4();
+    let mut uncategorized = Uncategorized::find(books);
+    let mut report = ImportReport::default();
+    for (i, line) in lines.enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let fields = csv_fields(line);
+        let account = |column: usize| match fields.get(column).map(|f| f.trim()).filter(|f| !f.is_empty()) {
+            Some(value) => Uuid::parse_str(value).map(Some).map_err(|_| format!("bad account id {}", value)),
+            None => Ok(None),
+        };
+        let result = CsvRow::parse(&fields, date, description, amount)
+            .and_then(|row| {
+                let (debit, credit) = (account(debit_column)?, account(credit_column)?);
+                let mut debit = uncategorized.or_account(books, debit).map_err(|e| e.error)?;
+                let mut credit = uncategorized.or_account(books, credit).map_err(|e| e.error)?;
+                if row.amount.is_sign_negative() {
+                    std::mem::swap(&mut debit, &mut credit);
+                }
+                Ok(row.to_transaction(debit, credit, batch_id))
+            })
+            .and_then(|t| books.add_transaction(t).map_err(|e| e.error));
+        match result {
+            Ok(()) => report.imported += 1,
+            Err(reason) => report.failed.push((i + 2, reason)),
+        }
+    }
+    uncategorized.remove_if_unused(books)?;
+    Ok(report)
+}
+
+/// Import bank transactions for an account from CSV with a header row and columns date (YYYY-MM-DD),
+/// description, amount and, optionally, the name of the counter account. Positive amounts are paid into
+/// the account. Rows without a counter account are posted against the Uncategorized expense account,
+/// which is created if missing. The transactions share an import batch id in their source. Nothing is
+/// imported if any row is invalid or rejected by the books. Returns the ids of the new transactions.
+pub fn import_transactions_csv(books: &mut Books, csv: &str, account_id: Uuid) -> Result<Vec<Uuid>, BooksError> {
+    let accounts = books.accounts();
+    let account = match accounts.iter().find(|a| a.id == account_id) {
+        Some(a) => a,
+        None => return Err(BooksError::from_str(format!("Account not found for id {}", account_id).as_str())),
+    };
+
+    let mut rows: Vec<(usize, CsvRow, Option<Uuid>)> = Vec::new();
+    for (i, line) in csv.lines().enumerate().skip(1).filter(|(_, l)| !l.trim().is_empty()) {
+        let invalid = |reason: &str| BooksError::from_str(format!("Invalid row {}: {}", i + 1, reason).as_str());
+        let fields = csv_fields(line);
+        let row = CsvRow::parse(&fields, 0, 1, 2).map_err(|reason| invalid(&reason))?;
+        let counter = match fields.get(3).map(|f| f.trim()).filter(|f| !f.is_empty()) {
+            Some(name) => match accounts.iter().find(|a| a.name == name) {
+                Some(a) => Some(a.id),
+                None => return Err(invalid(&format!("unknown account {}", name))),
+            },
+            None => None,
+        };
+        rows.push((i + 1, row, counter));
+    }
+
+    let batch_id = Uuid::new_v4();
+    let mut uncategorized = Uncategorized::find(books);
+    let mut transactions = Vec::new();
+    for (line, row, counter) in rows {
+        let counter = uncategorized.or_account(books, counter)?;
+        let side = if row.amount.is_sign_negative() { account.normal_balance().opposite() } else { account.normal_balance() };
+        let transaction = match side {
+            Side::Debit => row.to_transaction(account_id, counter, batch_id),
+            Side::Credit => row.to_transaction(counter, account_id, batch_id),
+        };
+        transactions.push((line, transaction));
+    }
+
+    // Nothing is added unless every row is valid, so an Uncategorized account created for the rows is
+    // deleted again if any is rejected.
+    for (line, transaction) in &transactions {
+        if let Err(e) = books.check_transaction(transaction) {
+            uncategorized.remove_if_unused(books)?;
+            return Err(BooksError::from_str(format!("Invalid row {}: {}", line, e.error).as_str()))
+        }
+    }
+    let ids = transactions.iter().map(|(_, t)| t.id).collect();
+    for (_, transaction) in transactions {
+        books.add_transaction(transaction)?;
+    }
+    Ok(ids)
+}
+
+/// Split a CSV line into fields, allowing quoted fields containing commas and doubled quotes.
+pub(crate) fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                fields.last_mut().unwrap().push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Quote a CSV field if it contains a comma, quote or line break.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    use crate::account::{Account, AccountType, Side, TransactionSource};
+    use crate::books::Books;
+    use super::{import_transactions, import_transactions_csv, CsvMapping, ImportReport, UNCATEGORIZED};
+
+    fn mapping() -> CsvMapping {
+        CsvMapping {
+            date: "Date".to_string(),
+            description: "Memo".to_string(),
+            amount: "Amount".to_string(),
+            debit_account: "To".to_string(),
+            credit_account: "From".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_import_transactions() {
+        let mut books = Books::build_empty("My Books");
+        let bank = Account::create_new("Bank", AccountType::Asset);
+        let groceries = Account::create_new("Groceries", AccountType::Expense);
+        let (bank_id, groceries_id) = (bank.id, groceries.id);
+        books.add_account(bank).unwrap();
+        books.add_account(groceries).unwrap();
+        let csv = format!("Memo,Date,Amount,To,From\n\
+            \"Supermarket, Main St\",2022-06-02,$82.50,{groceries},{bank}\n\
+            Refund,2022-06-03,-10,{groceries},{bank}\n", groceries = groceries_id, bank = bank_id);
+
+        assert_eq!(ImportReport { imported: 2, failed: Vec::new() }, import_transactions(&mut books, csv.as_bytes(), mapping()).unwrap());
+        assert_eq!(dec!(72.50), books.balance_as_of(groceries_id, NaiveDate::from_ymd(2022, 6, 30)).unwrap());
+        assert_eq!("Supermarket, Main St", books.transactions()[0].entries[0].description);
+    }
+
+    #[test]
+    fn test_import_transactions_failed_rows() {
+        let mut books = Books::build_empty("My Books");
+        let bank = Account::create_new("Bank", AccountType::Asset);
+        let groceries = Account::create_new("Groceries", AccountType::Expense);
+        let (bank_id, groceries_id) = (bank.id, groceries.id);
+        books.add_account(bank).unwrap();
+        books.add_account(groceries).unwrap();
+        let missing = Uuid::new_v4();
+        let csv = format!("Memo,Date,Amount,To,From\n\
+            Supermarket,2022-06-02,82.50,{groceries},{bank}\n\
+            Bakery,02/06/2022,5,{groceries},{bank}\n\
+            Butcher,2022-06-04,20,{missing},{bank}\n", groceries = groceries_id, bank = bank_id, missing = missing);
+
+        let report = import_transactions(&mut books, csv.as_bytes(), mapping()).unwrap();
+        assert_eq!(1, report.imported);
+        assert_eq!(vec![(3, "bad date 02/06/2022".to_string()), (4, format!("Account not found for id: {}", missing))], report.failed);
+        assert_eq!(1, books.transactions().len());
+
+        let mut wrong = mapping();
+        wrong.date = "When".to_string();
+        assert_eq!("Column When not found", import_transactions(&mut books, csv.as_bytes(), wrong).err().unwrap().error);
+    }
+
+    #[test]
+    fn test_import_transactions_uncategorized() {
+        let mut books = Books::build_empty("My Books");
+        let bank = Account::create_new("Bank", AccountType::Asset);
+        let bank_id = bank.id;
+        books.add_account(bank).unwrap();
+        // The row is rejected once the Uncategorized account has been created for it, so it's removed again.
+        let csv = format!("Memo,Date,Amount,To,From\n\
+            Bakery,2022-06-01,5,,{missing}\n", missing = Uuid::new_v4());
+        assert_eq!(0, import_transactions(&mut books, csv.as_bytes(), mapping()).unwrap().imported);
+        assert_eq!(1, books.accounts().len());
+
+        let csv = format!("Memo,Date,Amount,To,From\n\
+            Supermarket,2022-06-02,82.50,,{bank}\n\
+            Refund,2022-06-03,-10,,{bank}\n", bank = bank_id);
+        assert_eq!(2, import_transactions(&mut books, csv.as_bytes(), mapping()).unwrap().imported);
+        let uncategorized = books.accounts().into_iter().find(|a| a.name == UNCATEGORIZED).unwrap();
+        assert_eq!(dec!(72.50), books.balance_as_of(uncategorized.id, NaiveDate::from_ymd(2022, 6, 30)).unwrap());
+    }
+
+    #[test]
+    fn test_import_transactions_csv() {
+        let mut books = Books::build_empty("My Books");
+        let bank = Account::create_new("Bank", AccountType::Asset);
+        let bank_id = bank.id;
+        books.add_account(bank).unwrap();
+        let groceries = Account::create_new("Groceries", AccountType::Expense);
+        let groceries_id = groceries.id;
+        books.add_account(groceries).unwrap();
+        let csv = "date,description,amount,account\n\
+            2022-06-01,\"Pay, June\",\"$1,500.00\",\n\
+            2022-06-02,Supermarket,-82.50,Groceries\n\
+            2022-06-03,Coffee,-4.50\n";
+
+        let ids = import_transactions_csv(&mut books, csv, bank_id).unwrap();
+
+        assert_eq!(3, ids.len());
+        let uncategorized = books.accounts().into_iter().find(|a| a.name == UNCATEGORIZED).unwrap();
+        let pay = books.transaction(ids[0]).unwrap();
+        assert_eq!("Pay, June", pay.entries[0].description);
+        assert_eq!(Side::Debit, pay.account_entries(bank_id)[0].entry_type);
+        assert_eq!(dec!(1500.00), pay.account_entries(uncategorized.id)[0].amount);
+        assert_eq!(Side::Credit, pay.account_entries(uncategorized.id)[0].entry_type);
+        assert_eq!(Side::Debit, books.transaction(ids[1]).unwrap().account_entries(groceries_id)[0].entry_type);
+        assert!(books.transaction(ids[2]).unwrap().involves_account(&uncategorized.id));
+        assert_eq!(3, books.accounts().len());
+
+        let date = NaiveDate::from_ymd(2022, 6, 30);
+        assert_eq!(dec!(1413.00), books.balance_as_of(bank_id, date).unwrap());
+        let batch_id = match pay.source {
+            TransactionSource::Import { batch_id } => batch_id,
+            source => panic!("expected an import source, got {:?}", source),
+        };
+        assert!(ids.iter().all(|id| books.transaction(*id).unwrap().source == TransactionSource::Import { batch_id }));
+        assert!(import_transactions_csv(&mut books, "date,description,amount,account\n2022-06-04,Rent,-900,Housing\n", bank_id).is_err());
+    }
+
+    #[test]
+    fn test_import_transactions_csv_rejected_row() {
+        let mut books = Books::build_empty("My Books");
+        let bank = Account::create_new("Bank", AccountType::Asset);
+        let bank_id = bank.id;
+        books.add_account(bank).unwrap();
+        books.settings.max_description_length = Some(20);
+        let csv = "date,description,amount\n\
+            2022-06-01,Pay,1500\n\
+            2022-06-02,Supermarket on the corner of Main St,-82.50\n";
+
+        let error = import_transactions_csv(&mut books, csv, bank_id).err().unwrap().error;
+        assert!(error.starts_with("Invalid row 3: Description is 36 characters long"), "{}", error);
+        assert!(books.transactions().is_empty());
+        assert_eq!(1, books.accounts().len());
+    }
+}
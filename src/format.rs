@@ -0,0 +1,279 @@
+//! Parsing and display of amounts, e.g. "$1,234.50" or "(100)", and export of the books as text.
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, RoundingStrategy};
+use uuid::Uuid;
+
+use crate::account::{Account, AccountType, Side};
+use crate::books::{Books, BooksError};
+use crate::csv_import::csv_field;
+use crate::minor_units::{exponent, DEFAULT_EXPONENT};
+
+const CURRENCY_SYMBOLS: [char; 7] = ['$', '€', '£', '¥', '₹', '¢', '₩'];
+
+/// Parse an amount, ignoring currency symbols, grouping separators and whitespace. An amount wrapped in
+/// parentheses is negative.
+pub fn parse_amount(input: &str) -> Result<Decimal, BooksError> {
+    let invalid = || BooksError::from_str(format!("Invalid amount: {}", input).as_str());
+    let trimmed = input.trim();
+    let (negative, trimmed) = match trimmed.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => (false, trimmed),
+    };
+    let cleaned: String = trimmed.chars()
+        .filter(|c| !c.is_whitespace() && *c != ',' && !CURRENCY_SYMBOLS.contains(c))
+        .collect();
+    if cleaned.is_empty() || (negative && cleaned.starts_with('-')) {
+        return Err(invalid())
+    }
+    let amount = Decimal::from_str(&cleaned).map_err(|_| invalid())?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Display an amount to the currency's decimal places, accounting style, with negatives in parentheses
+/// e.g. "(100.00)". Unknown currencies use two decimal places.
+pub fn format_accounting(amount: Decimal, currency: &str) -> String {
+    let (rounded, places) = round_for_currency(amount.abs(), currency);
+    let formatted = format!("{:.*}", places, rounded);
+    if amount.is_sign_negative() && !rounded.is_zero() {
+        format!("({})", formatted)
+    } else {
+        formatted
+    }
+}
+
+/// Display an amount to the currency's decimal places with a minus sign if negative, e.g. "-100.00".
+/// Unknown currencies use two decimal places.
+pub fn format_amount(amount: Decimal, currency: &str) -> String {
+    let (rounded, places) = round_for_currency(amount, currency);
+    format!("{:.*}", places, rounded)
+}
+
+fn round_for_currency(amount: Decimal, currency: &str) -> (Decimal, usize) {
+    let places = exponent(currency).unwrap_or(DEFAULT_EXPONENT);
+    (amount.round_dp_with_strategy(places, RoundingStrategy::MidpointNearestEven), places as usize)
+}
+
+/// Export the transactions in the plain text format of the Ledger CLI. Each transaction is written as its
+/// date and description followed by a posting per entry, with debits positive and credits negative in the
+/// decimal places of the account's currency. Credits keep a minus sign rather than the parentheses of
+/// `format_accounting`, as Ledger reads a parenthesised amount as an expression. Accounts are named under
+/// Ledger's top level accounts, e.g. "Assets:Savings".
+pub fn export_ledger_format(books: &Books) -> String {
+    let names: HashMap<Uuid, String> = books.accounts()
+        .into_iter()
+        .map(|a| (a.id, ledger_account_name(&a)))
+        .collect();
+
+    let mut ledger = String::new();
+    for t in books.transactions() {
+        let first = match t.entries.first() {
+            Some(e) => e,
+            None => continue,
+        };
+        let postings: Vec<(String, String)> = t.entries
+            .iter()
+            .map(|e| {
+                let name = names.get(&e.account_id).cloned().unwrap_or(e.account_id.to_string());
+                let currency = books.account_currency(&e.account_id).unwrap_or_default();
+                (name, format_amount(e.signed_amount(Side::Debit), &currency))
+            })
+            .collect();
+        let name_width = postings.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+        let amount_width = postings.iter().map(|(_, amount)| amount.len()).max().unwrap_or(0);
+
+        ledger.push_str(&format!("{} {}\n", first.date.format("%Y/%m/%d"), first.description));
+        for (name, amount) in postings {
+            ledger.push_str(&format!("    {:<name_width$}  {:>amount_width$}\n", name, amount));
+        }
+        ledger.push('\n');
+    }
+    ledger
+}
+
+/// Display the balance sheet as of the given date as text, with each account under its section followed
+/// by the section total. Amounts are in the default currency's accounting style, e.g. "(100.00)".
+pub fn export_balance_sheet(books: &Books, as_of: NaiveDate) -> String {
+    let sheet = books.balance_sheet(as_of);
+    let currency = books.settings.default_currency.as_str();
+    let amount = |a: Decimal| Some(format_accounting(a, currency));
+    let mut lines: Vec<(String, Option<String>)> = Vec::new();
+    for (section, accounts, total) in [("Assets", &sheet.assets, sheet.total_assets),
+                                       ("Liabilities", &sheet.liabilities, sheet.total_liabilities),
+                                       ("Equity", &sheet.equity, sheet.total_equity)] {
+        lines.push((section.to_string(), None));
+        lines.extend(accounts.iter().map(|a| (format!("    {}", a.name), amount(a.balance))));
+        if section == "Equity" {
+            lines.push(("    Net income".to_string(), amount(sheet.net_income)));
+        }
+        lines.push((format!("Total {}", section.to_lowercase()), amount(total)));
+    }
+    let name_width = lines.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    let amount_width = lines.iter().filter_map(|(_, amount)| amount.as_ref().map(|a| a.len())).max().unwrap_or(0);
+
+    let mut text = format!("Balance sheet as of {}\n", as_of);
+    for (name, amount) in lines {
+        match amount {
+            Some(amount) => text.push_str(&format!("{:<name_width$}  {:>amount_width$}\n", name, amount)),
+            None => text.push_str(&format!("{}\n", name)),
+        }
+    }
+    text
+}
+
+/// Export the trial balance as of the given date as CSV, with columns for the account code, name, debit
+/// and credit. Each balance goes in the column of the side it is on, followed by a row of totals.
+pub fn export_trial_balance_csv(books: &Books, as_of: NaiveDate) -> String {
+    let balances: HashMap<Uuid, Decimal> = books.trial_balance(as_of).into_iter().map(|r| (r.account_id, r.balance)).collect();
+    let mut csv = String::from("code,name,debit,credit\n");
+    let mut debits = Decimal::ZERO;
+    let mut credits = Decimal::ZERO;
+    for a in books.accounts() {
+        let balance = balances[&a.id];
+        let side = if balance.is_sign_negative() { a.normal_balance().opposite() } else { a.normal_balance() };
+        let (debit, credit) = match side {
+            Side::Debit => (balance.abs().to_string(), String::new()),
+            Side::Credit => (String::new(), balance.abs().to_string()),
+        };
+        match side {
+            Side::Debit => debits += balance.abs(),
+            Side::Credit => credits += balance.abs(),
+        }
+        csv.push_str(&format!("{},{},{},{}\n", csv_field(&a.code.unwrap_or_default()), csv_field(&a.name), debit, credit));
+    }
+    csv.push_str(&format!(",Total,{},{}\n", debits, credits));
+    csv
+}
+
+fn ledger_account_name(account: &Account) -> String {
+    let top_level = match account.account_type {
+        AccountType::Asset => "Assets",
+        AccountType::Liability => "Liabilities",
+        AccountType::Revenue => "Income",
+        AccountType::Expense => "Expenses",
+        AccountType::Equity => "Equity",
+    };
+    // Ledger ends an account name at two spaces or a tab.
+    let name = account.name.split_whitespace().collect::<Vec<&str>>().join(" ");
+    format!("{}:{}", top_level, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rust_decimal_macros::dec;
+
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    use crate::account::{Account, AccountType, Entry, Side, Transaction, TransactionKind, TransactionSource, TransactionStatus};
+    use crate::books::Books;
+    use super::{export_balance_sheet, export_ledger_format, export_trial_balance_csv, format_accounting, format_amount, parse_amount};
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(dec!(1234.50), parse_amount("$1,234.50").unwrap());
+        assert_eq!(dec!(1234.5), parse_amount(" $1234.5 ").unwrap());
+        assert_eq!(dec!(-100), parse_amount("(100)").unwrap());
+        assert_eq!(dec!(-12.30), parse_amount("-€12.30").unwrap());
+    }
+
+    #[test]
+    fn test_parse_amount_invalid() {
+        assert_eq!("Invalid amount: abc", parse_amount("abc").err().unwrap().error);
+        assert!(parse_amount("").is_err());
+        assert!(parse_amount("$").is_err());
+        assert!(parse_amount("1.2.3").is_err());
+        assert!(parse_amount("(-5)").is_err());
+    }
+
+    #[test]
+    fn test_format_accounting() {
+        assert_eq!("(100.00)", format_accounting(dec!(-100), "USD"));
+        assert_eq!("100.00", format_accounting(dec!(100), "USD"));
+        assert_eq!("(1500)", format_accounting(dec!(-1500), "JPY"));
+        assert_eq!("0.00", format_accounting(dec!(-0.001), "XXXX"));
+        assert_eq!("-12.346", format_amount(dec!(-12.3456), "BHD"));
+    }
+
+    #[test]
+    fn test_export_balance_sheet() {
+        let mut books = Books::build_empty("My Books");
+        books.settings.default_currency = "USD".to_string();
+        for (name, account_type, starting_balance) in [("Bank", AccountType::Asset, dec!(1500.5)), ("Card", AccountType::Liability, dec!(-200)),
+                                                       ("Capital", AccountType::Equity, dec!(1700.5))] {
+            let mut account = Account::create_new(name, account_type);
+            account.starting_balance = starting_balance;
+            books.add_account(account).unwrap();
+        }
+
+        let expected = "Balance sheet as of 2022-06-30\n\
+            Assets\n\
+            \x20   Bank            1500.50\n\
+            Total assets        1500.50\n\
+            Liabilities\n\
+            \x20   Card           (200.00)\n\
+            Total liabilities  (200.00)\n\
+            Equity\n\
+            \x20   Capital         1700.50\n\
+            \x20   Net income         0.00\n\
+            Total equity        1700.50\n";
+        assert_eq!(expected, export_balance_sheet(&books, NaiveDate::from_ymd(2022, 6, 30)));
+    }
+
+    #[test]
+    fn test_export_ledger_format() {
+        let mut books = Books::build_empty("My Books");
+        let bank = Account::create_new("Bank", AccountType::Asset);
+        let bank_id = bank.id;
+        books.add_account(bank).unwrap();
+        let salary = Account::create_new("Salary  Income", AccountType::Revenue);
+        let salary_id = salary.id;
+        books.add_account(salary).unwrap();
+
+        let transaction_id = Uuid::new_v4();
+        let date = NaiveDate::from_ymd(2022, 6, 4);
+        let entry = |account_id, entry_type| Entry {
+            id: Uuid::new_v4(), transaction_id, date, description: "Pay day".to_string(), account_id, entry_type,
+            amount: dec!(1500.50), balance: None, currency: None, foreign_amount: None, reconciled: false, exclude_from_reports: false, dimensions: HashMap::new(),
+        };
+        books.add_transaction(Transaction {
+            id: transaction_id,
+            entries: vec![entry(bank_id, Side::Debit), entry(salary_id, Side::Credit)],
+            status: TransactionStatus::Recorded,
+            source: TransactionSource::Manual,
+            kind: TransactionKind::Normal,
+            approved: true,
+            related: Vec::new(),
+        }).unwrap();
+
+        let expected = "2022/06/04 Pay day\n    \
+            Assets:Bank            1500.50\n    \
+            Income:Salary Income  -1500.50\n\n";
+        assert_eq!(expected, export_ledger_format(&books));
+    }
+
+    #[test]
+    fn test_export_trial_balance_csv() {
+        let mut books = Books::build_empty("My Books");
+        let mut bank = Account::create_new("Bank", AccountType::Asset);
+        bank.code = Some("1000".to_string());
+        bank.starting_balance = dec!(250);
+        books.add_account(bank).unwrap();
+        let mut equity = Account::create_new("Owner's equity, opening", AccountType::Equity);
+        equity.code = Some("3000".to_string());
+        equity.starting_balance = dec!(250);
+        books.add_account(equity).unwrap();
+
+        let csv = export_trial_balance_csv(&books, NaiveDate::from_ymd(2022, 6, 30));
+
+        let expected = "code,name,debit,credit\n\
+            1000,Bank,250,\n\
+            3000,\"Owner's equity, opening\",,250\n\
+            ,Total,250,250\n";
+        assert_eq!(expected, csv);
+    }
+}
@@ -1,8 +1,11 @@
 #![allow(dead_code)]
+#![allow(deprecated)]
 
 use chrono::{NaiveDate};
 use crate::account::{Schedule, Transaction};
+use crate::scheduler::Scheduler;
 
+#[deprecated(note = "use Scheduler instead, converting with Scheduler::from")]
 pub struct Generator {
     pub scheduled_transations: Vec<Schedule>
 }
@@ -18,59 +21,108 @@ impl Generator {
                 next = schedule.schedule_next(end_date);
             }
         }
-        transactions.sort_by(|a, b| a.date.cmp(&b.date));
+        transactions.sort_by(|a, b| a.date().cmp(&b.date()));
         return transactions
     }
 }
 
+/// Move a generator's schedules into a scheduler, keeping their order.
+impl From<Generator> for Scheduler {
+    fn from(generator: Generator) -> Scheduler {
+        let mut scheduler = Scheduler::build_empty();
+        generator.scheduled_transations.into_iter().for_each(|s| scheduler.add_schedule(s));
+        scheduler
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use chrono::{NaiveDate};
+    use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
     use uuid::Uuid;
 
-    use crate::account::{ScheduleEnum, Schedule};
+    use crate::account::*;
+    use crate::calendar::BusinessDayAdjustment;
     use crate::generator::Generator;
+    use crate::scheduler::Scheduler;
+
+    fn build_schedule(name: &str, period: ScheduleEnum, frequency: i64, amount: Decimal, description: &str) -> Schedule {
+        let id = Uuid::new_v4();
+        Schedule{
+            id: id,
+            name: name.to_string(),
+            period: period,
+            frequency: frequency,
+            start_date: NaiveDate::from_ymd(2022, 3, 11),
+            end_date: None,
+            last_date: None,
+            skip_dates: Vec::new(),
+            month_end_policy: MonthEndPolicy::Clamp,
+            modifier: None,
+            active: true,
+            notes: None,
+            max_occurrences: None,
+            occurrence_count: 0,
+            business_day_rule: BusinessDayAdjustment::None,
+            holidays: Vec::new(),
+            entries: vec![
+                ScheduleEntry {
+                    amount: amount,
+                    description: description.to_string(),
+                    account_id: Uuid::new_v4(),
+                    entry_type: Side::Debit,
+                    schedule_id: id,
+                    step_schedule: StepSchedule::default(),
+                },
+                ScheduleEntry {
+                    amount: amount,
+                    description: description.to_string(),
+                    account_id: Uuid::new_v4(),
+                    entry_type: Side::Credit,
+                    schedule_id: id,
+                    step_schedule: StepSchedule::default(),
+                }
+            ]
+        }
+    }
+
+    fn build_generator() -> Generator {
+        let st1 = build_schedule("ST 1", ScheduleEnum::Months, 3, dec!(100.99), "st test 1");
+        let st2 = build_schedule("ST 2", ScheduleEnum::Days, 45, dec!(20.23), "st test 2");
+        Generator{scheduled_transations: vec!{st1, st2}}
+    }
 
     #[test]
     fn test_generate() {
-        let st1 = Schedule{
-            id: Uuid::new_v4(),
-            name: "ST 1".to_string(),
-            period: ScheduleEnum::Months,
-            frequency: 3,
-            start_date:   NaiveDate::from_ymd(2022, 3, 11),
-            last_date:   Some(NaiveDate::from_ymd(2022, 3, 11)),
-            amount:      dec!(100.99),
-            description: "st test 1".to_string(),
-            dr_account_id: Some(Uuid::new_v4()),
-            cr_account_id: Some(Uuid::new_v4())
-        };
-
-        let st2 = Schedule{
-            id: Uuid::new_v4(),
-            name: "ST 2".to_string(),
-            period: ScheduleEnum::Days,
-            frequency: 45,
-            start_date:   NaiveDate::from_ymd(2022, 3, 11),
-            last_date:   Some(NaiveDate::from_ymd(2022, 3, 11)),
-            amount:      dec!(20.23),
-            description: "st test 2".to_string(),
-            dr_account_id: Some(Uuid::new_v4()),
-            cr_account_id: Some(Uuid::new_v4())
-        };
-
-
-
-        let mut generator = Generator{scheduled_transations: vec!{st1, st2}.as_slice()};
-
+        let mut generator = build_generator();
 
         let max_date = NaiveDate::from_ymd(2023, 3, 11);
         let transactions = generator.generate(max_date);
 
-        assert_eq!(12, transactions.len());
-        assert_eq!("st test 2", transactions[0].description);
-        assert_eq!("st test 1", transactions[2].description);
+        assert_eq!(14, transactions.len());
+        assert_eq!("st test 1", transactions[0].entries[0].description);
+        assert_eq!("st test 2", transactions[2].entries[0].description);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_into_scheduler() {
+        let mut generator = build_generator();
+        let mut scheduler = Scheduler::from(build_generator());
+        assert_eq!(2, scheduler.schedules().len());
+        assert_eq!("ST 1", scheduler.schedules()[0].name);
+        assert_eq!("ST 2", scheduler.schedules()[1].name);
+
+        let max_date = NaiveDate::from_ymd(2023, 3, 11);
+        let expected = generator.generate(max_date);
+        let transactions = scheduler.generate(max_date);
+
+        assert_eq!(expected.len(), transactions.len());
+        for (e, t) in expected.iter().zip(transactions.iter()) {
+            assert_eq!(e.date(), t.date());
+            assert_eq!(e.entries[0].description, t.entries[0].description);
+            assert_eq!(e.entries[0].amount, t.entries[0].amount);
+        }
+    }
+}
@@ -2,4 +2,10 @@ pub mod account;
 pub mod books;
 pub mod book_repo;
 pub mod serializer;
+pub mod minor_units;
+pub mod reconciliation;
 pub mod scheduler;
+pub mod generator;
+pub mod format;
+pub mod calendar;
+pub mod csv_import;
@@ -0,0 +1,270 @@
+//! Conversion of amounts to and from integer minor units (e.g. cents) using the number of decimal places
+//! of the ISO currency. With the `integer_amounts` feature every amount of an entry or account is held as
+//! `i64` minor units in its stored form, see `StoredEntry` and `StoredAccount`, and converted to and from
+//! the `Decimal` fields when books are saved and loaded.
+use rust_decimal::Decimal;
+use rusty_money::iso;
+
+use crate::books::BooksError;
+#[cfg(feature = "integer_amounts")]
+pub(crate) use storage::{StoredAccount, StoredEntry};
+
+/// Exponent used when no currency is known, i.e. amounts are stored in hundredths.
+pub const DEFAULT_EXPONENT: u32 = 2;
+
+pub fn exponent(currency: &str) -> Result<u32, BooksError> {
+    match iso::find(currency) {
+        Some(c) => Ok(c.exponent),
+        None => Err(BooksError::from_str(format!("Unknown currency: {}", currency).as_str())),
+    }
+}
+
+/// Convert an amount to minor units, failing if the amount has more decimal places than the currency.
+pub fn to_minor_units(amount: Decimal, currency: &str) -> Result<i64, BooksError> {
+    to_minor_units_with_exponent(amount, exponent(currency)?)
+}
+
+pub fn from_minor_units(units: i64, currency: &str) -> Result<Decimal, BooksError> {
+    Ok(Decimal::new(units, exponent(currency)?))
+}
+
+pub fn to_minor_units_with_exponent(amount: Decimal, exponent: u32) -> Result<i64, BooksError> {
+    let mut scaled = amount;
+    scaled.rescale(exponent);
+    if scaled != amount {
+        return Err(BooksError::from_str(format!("Amount {} has more than {} decimal places", amount, exponent).as_str()))
+    }
+    i64::try_from(scaled.mantissa())
+        .map_err(|_| BooksError::from_str(format!("Amount {} is too large", amount).as_str()))
+}
+
+/// Number of decimal places the amounts of a stored entry or account are written with: the currency's
+/// exponent, or more if any of the amounts is more precise, so storing never rounds. An amount too precise
+/// to fit in `i64` minor units, e.g. an unrounded 10 / 3, can't be stored.
+pub fn storage_exponent(currency: Option<&str>, amounts: &[Decimal]) -> u32 {
+    let currency_exponent = currency.and_then(|c| exponent(c).ok()).unwrap_or(DEFAULT_EXPONENT);
+    amounts.iter().map(|a| a.normalize().scale()).fold(currency_exponent, u32::max)
+}
+
+#[cfg(feature = "integer_amounts")]
+mod storage {
+    use std::collections::HashMap;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Serialize, Serializer};
+    use uuid::Uuid;
+
+    use crate::account::{Account, AccountCategory, AccountType, Entry, Side};
+    use crate::books::BooksError;
+    use crate::serializer::*;
+    use super::{storage_exponent, to_minor_units_with_exponent, DEFAULT_EXPONENT};
+
+    /// An Entry as saved, with the amount, balance and foreign amount in minor units of `exponent` decimal
+    /// places.
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct StoredEntry {
+        id: Uuid,
+        transaction_id: Uuid,
+        #[serde(serialize_with = "serialize_naivedate")]
+        #[serde(deserialize_with = "deserialize_naivedate")]
+        date: NaiveDate,
+        description: String,
+        account_id: Uuid,
+        entry_type: Side,
+        /// Files saved before the exponent was stored always used hundredths.
+        #[serde(default = "default_exponent")]
+        exponent: u32,
+        amount: i64,
+        balance: Option<i64>,
+        #[serde(default)]
+        currency: Option<String>,
+        #[serde(default)]
+        foreign_amount: Option<i64>,
+        #[serde(default)]
+        reconciled: bool,
+        #[serde(default)]
+        exclude_from_reports: bool,
+        #[serde(default)]
+        dimensions: HashMap<String, String>,
+    }
+
+    /// An Account as saved, with its balances and budget in minor units of `exponent` decimal places.
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct StoredAccount {
+        id: Uuid,
+        name: String,
+        account_type: AccountType,
+        #[serde(default = "default_exponent")]
+        exponent: u32,
+        balance: i64,
+        starting_balance: i64,
+        #[serde(default)]
+        #[serde(serialize_with = "serialize_option_naivedate")]
+        #[serde(deserialize_with = "deserialize_option_naivedate")]
+        starting_balance_date: Option<NaiveDate>,
+        #[serde(default)]
+        #[serde(serialize_with = "serialize_option_naivedate")]
+        #[serde(deserialize_with = "deserialize_option_naivedate")]
+        opened: Option<NaiveDate>,
+        #[serde(default)]
+        currency: Option<String>,
+        #[serde(default)]
+        code: Option<String>,
+        #[serde(default)]
+        monthly_budget: Option<i64>,
+        #[serde(default = "default_true")]
+        active: bool,
+        #[serde(default)]
+        category: Option<AccountCategory>,
+    }
+
+    fn default_exponent() -> u32 {
+        DEFAULT_EXPONENT
+    }
+
+    fn default_true() -> bool {
+        true
+    }
+
+    fn from_units(units: i64, exponent: u32) -> Result<Decimal, String> {
+        Decimal::try_new(units, exponent).map_err(|e| format!("Invalid amount {}e-{}: {}", units, exponent, e))
+    }
+
+    impl TryFrom<&Entry> for StoredEntry {
+        type Error = BooksError;
+
+        fn try_from(e: &Entry) -> Result<StoredEntry, BooksError> {
+            let amounts: Vec<Decimal> = Some(e.amount).into_iter().chain(e.balance).chain(e.foreign_amount).collect();
+            let exponent = storage_exponent(e.currency.as_deref(), &amounts);
+            Ok(StoredEntry {
+                id: e.id,
+                transaction_id: e.transaction_id,
+                date: e.date,
+                description: e.description.clone(),
+                account_id: e.account_id,
+                entry_type: e.entry_type,
+                exponent,
+                amount: to_minor_units_with_exponent(e.amount, exponent)?,
+                balance: e.balance.map(|b| to_minor_units_with_exponent(b, exponent)).transpose()?,
+                currency: e.currency.clone(),
+                foreign_amount: e.foreign_amount.map(|f| to_minor_units_with_exponent(f, exponent)).transpose()?,
+                reconciled: e.reconciled,
+                exclude_from_reports: e.exclude_from_reports,
+                dimensions: e.dimensions.clone(),
+            })
+        }
+    }
+
+    impl TryFrom<StoredEntry> for Entry {
+        type Error = String;
+
+        fn try_from(e: StoredEntry) -> Result<Entry, String> {
+            Ok(Entry {
+                id: e.id,
+                transaction_id: e.transaction_id,
+                date: e.date,
+                description: e.description,
+                account_id: e.account_id,
+                entry_type: e.entry_type,
+                amount: from_units(e.amount, e.exponent)?,
+                balance: e.balance.map(|b| from_units(b, e.exponent)).transpose()?,
+                currency: e.currency,
+                foreign_amount: e.foreign_amount.map(|f| from_units(f, e.exponent)).transpose()?,
+                reconciled: e.reconciled,
+                exclude_from_reports: e.exclude_from_reports,
+                dimensions: e.dimensions,
+            })
+        }
+    }
+
+    impl Serialize for Entry {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            StoredEntry::try_from(self).map_err(|e| serde::ser::Error::custom(e.error))?.serialize(serializer)
+        }
+    }
+
+    impl TryFrom<&Account> for StoredAccount {
+        type Error = BooksError;
+
+        fn try_from(a: &Account) -> Result<StoredAccount, BooksError> {
+            let amounts: Vec<Decimal> = [a.balance, a.starting_balance].into_iter().chain(a.monthly_budget).collect();
+            let exponent = storage_exponent(a.currency.as_deref(), &amounts);
+            Ok(StoredAccount {
+                id: a.id,
+                name: a.name.clone(),
+                account_type: a.account_type.clone(),
+                exponent,
+                balance: to_minor_units_with_exponent(a.balance, exponent)?,
+                starting_balance: to_minor_units_with_exponent(a.starting_balance, exponent)?,
+                starting_balance_date: a.starting_balance_date,
+                opened: a.opened,
+                currency: a.currency.clone(),
+                code: a.code.clone(),
+                monthly_budget: a.monthly_budget.map(|b| to_minor_units_with_exponent(b, exponent)).transpose()?,
+                active: a.active,
+                category: a.category.clone(),
+            })
+        }
+    }
+
+    impl TryFrom<StoredAccount> for Account {
+        type Error = String;
+
+        fn try_from(a: StoredAccount) -> Result<Account, String> {
+            Ok(Account {
+                id: a.id,
+                name: a.name,
+                account_type: a.account_type,
+                balance: from_units(a.balance, a.exponent)?,
+                starting_balance: from_units(a.starting_balance, a.exponent)?,
+                starting_balance_date: a.starting_balance_date,
+                opened: a.opened,
+                currency: a.currency,
+                code: a.code,
+                monthly_budget: a.monthly_budget.map(|b| from_units(b, a.exponent)).transpose()?,
+                active: a.active,
+                category: a.category,
+            })
+        }
+    }
+
+    impl Serialize for Account {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            StoredAccount::try_from(self).map_err(|e| serde::ser::Error::custom(e.error))?.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::{from_minor_units, storage_exponent, to_minor_units, to_minor_units_with_exponent};
+
+    #[test]
+    fn test_round_trip() {
+        for (amount, currency, units) in [(dec!(1234.50), "USD", 123450), (dec!(-0.01), "EUR", -1), (dec!(1500), "JPY", 1500), (dec!(12.345), "BHD", 12345)] {
+            assert_eq!(units, to_minor_units(amount, currency).unwrap());
+            assert_eq!(amount, from_minor_units(units, currency).unwrap());
+        }
+        assert_eq!(12300, to_minor_units(dec!(123), "USD").unwrap());
+    }
+
+    #[test]
+    fn test_too_precise() {
+        assert_eq!("Amount 1.005 has more than 2 decimal places", to_minor_units(dec!(1.005), "USD").err().unwrap().error);
+        assert!(to_minor_units(dec!(1.5), "JPY").is_err());
+        assert!(to_minor_units(dec!(1), "XXXX").is_err());
+        let third = dec!(10) / dec!(3);
+        assert_eq!(format!("Amount {} is too large", third), to_minor_units_with_exponent(third, third.scale()).err().unwrap().error);
+    }
+
+    #[test]
+    fn test_storage_exponent() {
+        assert_eq!(2, storage_exponent(Some("USD"), &[dec!(98.99), dec!(1.50)]));
+        assert_eq!(0, storage_exponent(Some("JPY"), &[dec!(1500)]));
+        assert_eq!(3, storage_exponent(Some("BHD"), &[dec!(12.3)]));
+        assert_eq!(2, storage_exponent(None, &[dec!(100.000)]));
+        assert_eq!(4, storage_exponent(Some("USD"), &[dec!(0.3333)]));
+    }
+}
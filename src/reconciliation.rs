@@ -0,0 +1,36 @@
+//! Bank reconciliation sessions, recording which entries of an account have been cleared against a
+//! statement. The difference to the statement balance is worked out by `Books::reconciliation_difference`.
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::serializer::*;
+
+/// A bank reconciliation in progress: entries of an account are cleared against a statement until the
+/// cleared balance matches the statement's ending balance.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ReconciliationSession {
+    pub account_id: Uuid,
+    #[serde(serialize_with = "serialize_naivedate")]
+    #[serde(deserialize_with = "deserialize_naivedate")]
+    pub statement_date: NaiveDate,
+    pub target_balance: Decimal,
+    pub cleared: HashSet<Uuid>,
+}
+
+impl ReconciliationSession {
+    pub fn new(account_id: Uuid, statement_date: NaiveDate, target_balance: Decimal) -> ReconciliationSession {
+        ReconciliationSession { account_id, statement_date, target_balance, cleared: HashSet::new() }
+    }
+
+    pub fn clear(&mut self, entry_id: Uuid) {
+        self.cleared.insert(entry_id);
+    }
+
+    pub fn unclear(&mut self, entry_id: &Uuid) {
+        self.cleared.remove(entry_id);
+    }
+}
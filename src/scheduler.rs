@@ -1,12 +1,13 @@
 use chrono::NaiveDate;
 use serde::{Serialize, Deserialize};
+use uuid::Uuid;
 use crate::serializer::*;
 
 use crate::{account::{Schedule, Transaction}, books::BooksError};
 
 ///
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Scheduler {
     schedules: Vec<Schedule>,
     #[serde(serialize_with = "serialize_option_naivedate")]
@@ -35,29 +36,62 @@ impl Scheduler {
 
     }
 
+    pub fn delete_schedule(&mut self, id: &Uuid) -> Result<(), BooksError> {
+        if let Some(index) = self.schedules.iter().position(|s| s.id == *id) {
+            self.schedules.remove(index);
+            Ok(())
+        } else {
+            Err(BooksError::from_str(format!("Schedule {} not found.", id).as_str()))
+        }
+    }
+
+    /// Reset every schedule to generate again from its start date.
+    pub fn reset(&mut self) {
+        self.schedules.iter_mut().for_each(|s| s.reset());
+        self.end_date = None;
+    }
+
     pub fn schedules(&self) -> &[Schedule] {
         self.schedules.as_slice()
     }
 
+    pub(crate) fn schedules_mut(&mut self) -> &mut [Schedule] {
+        self.schedules.as_mut_slice()
+    }
+
     pub fn end_date(&self) -> Option<NaiveDate> {
         self.end_date.and_then(|d| Some(d.clone()))
     }
 
     pub fn generate(&mut self, end_date: NaiveDate) -> Vec<Transaction> {
         let mut transactions : Vec<Transaction> = Vec::new();
-        self.end_date = Some(end_date);
 
-        for schedule in &mut self.schedules {
-            let mut next = schedule.schedule_next(end_date);
-            while next.is_some() {
-                transactions.push(next.unwrap());
-                next = schedule.schedule_next(end_date);
-            }
+        while let Some(transaction) = self.generate_next(end_date) {
+            transactions.push(transaction);
         }
-        transactions.sort_by(|a, b| a.entries[0].date.cmp(&b.entries[0].date));
         print!("{:?}", transactions);
         transactions
     }
+
+    /// Generate the earliest transaction due from any schedule up to the end date. Where schedules are due
+    /// on the same date the one added first goes first.
+    pub fn generate_next(&mut self, end_date: NaiveDate) -> Option<Transaction> {
+        self.generate_next_excluding(end_date, &[])
+    }
+
+    /// Generate the next transaction as `generate_next` does, ignoring the excluded schedules.
+    pub fn generate_next_excluding(&mut self, end_date: NaiveDate, excluded: &[Uuid]) -> Option<Transaction> {
+        self.end_date = Some(end_date);
+        let mut next: Option<(usize, NaiveDate)> = None;
+        for (i, schedule) in self.schedules.iter().enumerate().filter(|(_, s)| !excluded.contains(&s.id)) {
+            if let Some(date) = schedule.next_occurrence(end_date) {
+                if next.is_none_or(|(_, d)| date < d) {
+                    next = Some((i, date));
+                }
+            }
+        }
+        next.and_then(|(i, _)| self.schedules[i].schedule_next(end_date))
+    }
 }
 
 
@@ -68,7 +102,7 @@ mod tests {
     use uuid::Uuid;
     use chrono::{NaiveDate};
     use rust_decimal_macros::dec;
-    use crate::{account::*, scheduler::Scheduler};
+    use crate::{account::*, calendar::BusinessDayAdjustment, scheduler::Scheduler};
 
 
     #[test]
@@ -89,6 +123,15 @@ mod tests {
                 start_date: NaiveDate::from_ymd(2022, 3, 11),
                 end_date: None,
                 last_date: None,
+                skip_dates: Vec::new(),
+                month_end_policy: MonthEndPolicy::Clamp,
+                modifier: None,
+                active: true,
+                notes: None,
+                max_occurrences: None,
+                occurrence_count: 0,
+                business_day_rule: BusinessDayAdjustment::None,
+                holidays: Vec::new(),
                 entries: vec![
                     ScheduleEntry {
                         amount: dec!(100.99),
@@ -96,6 +139,7 @@ mod tests {
                         account_id: id1,
                         entry_type: Side::Debit,
                         schedule_id: s_id_1,
+                        step_schedule: StepSchedule::default(),
                     },
                     ScheduleEntry {
                         amount: dec!(100.99),
@@ -103,6 +147,7 @@ mod tests {
                         account_id: id2,
                         entry_type: Side::Credit,
                         schedule_id: s_id_1,
+                        step_schedule: StepSchedule::default(),
                     }
                 ]
             });
@@ -117,6 +162,15 @@ mod tests {
                 start_date: NaiveDate::from_ymd(2022, 3, 11),
                 end_date: Some(NaiveDate::from_ymd(2023, 1, 20)),
                 last_date: None,
+                skip_dates: Vec::new(),
+                month_end_policy: MonthEndPolicy::Clamp,
+                modifier: None,
+                active: true,
+                notes: None,
+                max_occurrences: None,
+                occurrence_count: 0,
+                business_day_rule: BusinessDayAdjustment::None,
+                holidays: Vec::new(),
                 entries: vec![
                     ScheduleEntry {
                         amount: dec!(20.23),
@@ -124,6 +178,7 @@ mod tests {
                         account_id: id2,
                         entry_type: Side::Debit,
                         schedule_id: s_id_2,
+                        step_schedule: StepSchedule::default(),
                     },
                     ScheduleEntry {
                         amount: dec!(100.99),
@@ -131,6 +186,7 @@ mod tests {
                         account_id: id1,
                         entry_type: Side::Credit,
                         schedule_id: s_id_2,
+                        step_schedule: StepSchedule::default(),
                     }
                 ]
             });
@@ -38,7 +38,22 @@ pub fn serialize_option_naivedate<S>(date: &Option<NaiveDate>, serializer: S) ->
     
 }
 
+pub fn deserialize_vec_naivedate<'de, D>(deserializer: D) -> Result<Vec<NaiveDate>, D::Error>
+    where D: Deserializer<'de>
+{
+    let date_strs = Vec::<String>::deserialize(deserializer)?;
+    use serde::de::Error;
+    date_strs.iter()
+        .map(|d| parse_date_str(d).map_err(Error::custom))
+        .collect()
+}
+
+pub fn serialize_vec_naivedate<S>(dates: &[NaiveDate], serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+{
+    serializer.collect_seq(dates.iter().map(|d| d.to_string()))
+}
 
-fn parse_date_str(date_str: &String) -> Result<NaiveDate, ParseError> {
+pub(crate) fn parse_date_str(date_str: &String) -> Result<NaiveDate, ParseError> {
     NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
 }